@@ -0,0 +1,116 @@
+use super::{Arch, FrameSize, PagingLevel};
+use sel4_sys::*;
+use vspace::CachePolicy;
+
+/// seL4_ARM_PageCacheable
+const ARM_PAGE_CACHEABLE: seL4_ARM_VMAttributes = 0x01;
+/// seL4_ARM_ExecuteNever
+const ARM_EXECUTE_NEVER: seL4_ARM_VMAttributes = 0x04;
+
+pub struct Aarch64;
+
+impl Arch for Aarch64 {
+    type VMAttributes = seL4_ARM_VMAttributes;
+
+    const PAGE_OBJECT: seL4_ObjectType = _object_seL4_ARM_SmallPageObject;
+    const PAGE_BITS: usize = seL4_PageBits as _;
+
+    const FRAME_SIZES: &'static [FrameSize] = &[
+        FrameSize {
+            obj_type: _object_seL4_ARM_SmallPageObject,
+            size_bits: 12,
+        },
+        FrameSize {
+            obj_type: _object_seL4_ARM_LargePageObject,
+            size_bits: 21,
+        },
+        FrameSize {
+            obj_type: _mode_object_seL4_ARM_HugePageObject,
+            size_bits: 30,
+        },
+    ];
+
+    const PAGING_LEVELS: &'static [PagingLevel] = &[
+        PagingLevel {
+            obj_type: _mode_object_seL4_ARM_PageUpperDirectoryObject,
+            size_bits: seL4_PUDBits as _,
+            coverage_bits: 39,
+        },
+        PagingLevel {
+            obj_type: _object_seL4_ARM_PageDirectoryObject,
+            size_bits: seL4_PageDirBits as _,
+            coverage_bits: 30,
+        },
+        PagingLevel {
+            obj_type: _object_seL4_ARM_PageTableObject,
+            size_bits: seL4_PageTableBits as _,
+            coverage_bits: 21,
+        },
+    ];
+
+    /// well above the root task image, user addresses are limited to 48 bits
+    const VSPACE_START: seL4_Word = 0x1000_0000;
+    const VSPACE_END: seL4_Word = 0x0000_8000_0000_0000;
+
+    const UNKNOWN_SYSCALL_IP_MR: usize = 8;
+    const UNKNOWN_SYSCALL_NUMBER_MR: usize = 12;
+
+    const BADGE_BITS: usize = 64;
+
+    fn vm_attributes(cache: CachePolicy, executable: bool) -> seL4_ARM_VMAttributes {
+        // without PageCacheable the mapping is device memory,
+        // there is no separate write-combine attribute
+        let attribs = match cache {
+            CachePolicy::Cached => ARM_PAGE_CACHEABLE,
+            CachePolicy::Uncached | CachePolicy::WriteCombine => 0,
+        };
+
+        if executable {
+            attribs
+        } else {
+            attribs | ARM_EXECUTE_NEVER
+        }
+    }
+
+    unsafe fn page_map(
+        frame_cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        rights: seL4_CapRights_t,
+        attribs: seL4_ARM_VMAttributes,
+    ) -> seL4_Error {
+        seL4_ARM_Page_Map(frame_cap, vspace_cap, vaddr, rights, attribs)
+    }
+
+    unsafe fn page_unmap(frame_cap: seL4_CPtr) -> seL4_Error {
+        seL4_ARM_Page_Unmap(frame_cap)
+    }
+
+    unsafe fn paging_structure_map(
+        level: usize,
+        cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        attribs: seL4_ARM_VMAttributes,
+    ) -> seL4_Error {
+        match level {
+            0 => seL4_ARM_PageUpperDirectory_Map(cap, vspace_cap, vaddr, attribs),
+            1 => seL4_ARM_PageDirectory_Map(cap, vspace_cap, vaddr, attribs),
+            2 => seL4_ARM_PageTable_Map(cap, vspace_cap, vaddr, attribs),
+            _ => panic!("Invalid paging level {}", level),
+        }
+    }
+
+    fn init_user_context(
+        regs: &mut seL4_UserContext,
+        pc: seL4_Word,
+        sp: seL4_Word,
+        args: [seL4_Word; 3],
+    ) {
+        regs.pc = pc;
+        regs.sp = sp;
+        regs.x0 = args[0];
+        regs.x1 = args[1];
+        regs.x2 = args[2];
+    }
+}
@@ -0,0 +1,108 @@
+use super::{Arch, FrameSize, PagingLevel};
+use sel4_sys::*;
+use vspace::CachePolicy;
+
+/// seL4_ARM_PageCacheable
+const ARM_PAGE_CACHEABLE: seL4_ARM_VMAttributes = 0x01;
+/// seL4_ARM_ParityEnabled
+const ARM_PARITY_ENABLED: seL4_ARM_VMAttributes = 0x02;
+/// seL4_ARM_ExecuteNever
+const ARM_EXECUTE_NEVER: seL4_ARM_VMAttributes = 0x04;
+
+pub struct Armv7;
+
+impl Arch for Armv7 {
+    type VMAttributes = seL4_ARM_VMAttributes;
+
+    const PAGE_OBJECT: seL4_ObjectType = _object_seL4_ARM_SmallPageObject;
+    const PAGE_BITS: usize = seL4_PageBits as _;
+
+    const FRAME_SIZES: &'static [FrameSize] = &[
+        FrameSize {
+            obj_type: _object_seL4_ARM_SmallPageObject,
+            size_bits: 12,
+        },
+        FrameSize {
+            obj_type: _object_seL4_ARM_LargePageObject,
+            size_bits: 16,
+        },
+        FrameSize {
+            obj_type: _object_seL4_ARM_SectionObject,
+            size_bits: 20,
+        },
+        FrameSize {
+            obj_type: _object_seL4_ARM_SuperSectionObject,
+            size_bits: 24,
+        },
+    ];
+
+    const PAGING_LEVELS: &'static [PagingLevel] = &[PagingLevel {
+        obj_type: _object_seL4_ARM_PageTableObject,
+        size_bits: seL4_PageTableBits as _,
+        coverage_bits: 20,
+    }];
+
+    /// well above the root task image, the kernel window starts at VSPACE_END
+    const VSPACE_START: seL4_Word = 0x1000_0000;
+    const VSPACE_END: seL4_Word = 0xE000_0000;
+
+    const UNKNOWN_SYSCALL_IP_MR: usize = 8;
+    const UNKNOWN_SYSCALL_NUMBER_MR: usize = 12;
+
+    const BADGE_BITS: usize = 28;
+
+    fn vm_attributes(cache: CachePolicy, executable: bool) -> seL4_ARM_VMAttributes {
+        // without PageCacheable the mapping is strongly ordered device memory,
+        // there is no separate write-combine attribute
+        let attribs = match cache {
+            CachePolicy::Cached => ARM_PAGE_CACHEABLE | ARM_PARITY_ENABLED,
+            CachePolicy::Uncached | CachePolicy::WriteCombine => 0,
+        };
+
+        if executable {
+            attribs
+        } else {
+            attribs | ARM_EXECUTE_NEVER
+        }
+    }
+
+    unsafe fn page_map(
+        frame_cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        rights: seL4_CapRights_t,
+        attribs: seL4_ARM_VMAttributes,
+    ) -> seL4_Error {
+        seL4_ARM_Page_Map(frame_cap, vspace_cap, vaddr, rights, attribs)
+    }
+
+    unsafe fn page_unmap(frame_cap: seL4_CPtr) -> seL4_Error {
+        seL4_ARM_Page_Unmap(frame_cap)
+    }
+
+    unsafe fn paging_structure_map(
+        level: usize,
+        cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        attribs: seL4_ARM_VMAttributes,
+    ) -> seL4_Error {
+        match level {
+            0 => seL4_ARM_PageTable_Map(cap, vspace_cap, vaddr, attribs),
+            _ => panic!("Invalid paging level {}", level),
+        }
+    }
+
+    fn init_user_context(
+        regs: &mut seL4_UserContext,
+        pc: seL4_Word,
+        sp: seL4_Word,
+        args: [seL4_Word; 3],
+    ) {
+        regs.pc = pc;
+        regs.sp = sp;
+        regs.r0 = args[0];
+        regs.r1 = args[1];
+        regs.r2 = args[2];
+    }
+}
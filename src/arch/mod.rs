@@ -0,0 +1,130 @@
+//! Architecture specific paging objects, mapping invocations and
+//! user context setup, `CurrentArch` implements `Arch` for the target
+//! being built
+
+use sel4_sys::{
+    seL4_CPtr, seL4_CapRights_t, seL4_Error, seL4_ObjectType, seL4_UserContext, seL4_Word,
+};
+use vspace::CachePolicy;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "arm")]
+mod armv7;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub use self::aarch64::Aarch64 as CurrentArch;
+#[cfg(target_arch = "arm")]
+pub use self::armv7::Armv7 as CurrentArch;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::X86_64 as CurrentArch;
+
+/// A frame object the architecture can map
+#[derive(Debug, Copy, Clone)]
+pub struct FrameSize {
+    pub obj_type: seL4_ObjectType,
+    pub size_bits: usize,
+}
+
+/// A level of paging structures below the VSpace root
+#[derive(Debug, Copy, Clone)]
+pub struct PagingLevel {
+    pub obj_type: seL4_ObjectType,
+    pub size_bits: usize,
+    /// number of virtual address bits covered by a single structure
+    pub coverage_bits: usize,
+}
+
+pub trait Arch {
+    type VMAttributes: Copy;
+
+    /// Object type and size of a small (base) page frame
+    const PAGE_OBJECT: seL4_ObjectType;
+    const PAGE_BITS: usize;
+
+    /// Mappable frame objects, ordered from the small page up
+    const FRAME_SIZES: &'static [FrameSize];
+
+    /// Paging structures that must be present before a small page
+    /// can be mapped, ordered from the VSpace root down
+    const PAGING_LEVELS: &'static [PagingLevel];
+
+    /// Range of user virtual addresses free for the VSpaceManager to hand out
+    const VSPACE_START: seL4_Word;
+    const VSPACE_END: seL4_Word;
+
+    /// Message registers of the faulting instruction pointer and the syscall
+    /// number in an unknown syscall fault message, the registers before them
+    /// carry the thread's argument registers
+    const UNKNOWN_SYSCALL_IP_MR: usize;
+    const UNKNOWN_SYSCALL_NUMBER_MR: usize;
+
+    /// Number of badge bits the kernel keeps when minting an endpoint
+    /// or notification cap
+    const BADGE_BITS: usize;
+
+    /// VM attributes for a frame mapping with the given cache policy,
+    /// architectures without an execute-never attribute ignore `executable`
+    fn vm_attributes(cache: CachePolicy, executable: bool) -> Self::VMAttributes;
+
+    unsafe fn page_map(
+        frame_cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        rights: seL4_CapRights_t,
+        attribs: Self::VMAttributes,
+    ) -> seL4_Error;
+
+    unsafe fn page_unmap(frame_cap: seL4_CPtr) -> seL4_Error;
+
+    /// Maps a paging structure of PAGING_LEVELS[level] to cover vaddr
+    unsafe fn paging_structure_map(
+        level: usize,
+        cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        attribs: Self::VMAttributes,
+    ) -> seL4_Error;
+
+    /// Sets up a thread's registers to start executing at pc
+    /// on the stack at sp with args as the first three function arguments
+    fn init_user_context(
+        regs: &mut seL4_UserContext,
+        pc: seL4_Word,
+        sp: seL4_Word,
+        args: [seL4_Word; 3],
+    );
+}
+
+/// Returns the frame object of exactly 2^size_bits bytes
+pub fn frame_size(size_bits: usize) -> Option<FrameSize> {
+    CurrentArch::FRAME_SIZES
+        .iter()
+        .find(|f| f.size_bits == size_bits)
+        .cloned()
+}
+
+/// Returns the next frame object smaller than 2^size_bits bytes
+pub fn smaller_frame_size(size_bits: usize) -> Option<FrameSize> {
+    CurrentArch::FRAME_SIZES
+        .iter()
+        .rev()
+        .find(|f| f.size_bits < size_bits)
+        .cloned()
+}
+
+/// Returns the largest frame object that fits in size bytes and is aligned
+/// at vaddr and, if given, paddr. Falls back to the small page.
+pub fn largest_frame_size(paddr: Option<seL4_Word>, vaddr: seL4_Word, size: usize) -> FrameSize {
+    CurrentArch::FRAME_SIZES
+        .iter()
+        .rev()
+        .find(|f| {
+            let mask: seL4_Word = (1 << f.size_bits) - 1;
+            (1 << f.size_bits) <= size && vaddr & mask == 0 && paddr.map_or(true, |p| p & mask == 0)
+        })
+        .cloned()
+        .unwrap_or(CurrentArch::FRAME_SIZES[0])
+}
@@ -0,0 +1,115 @@
+use super::{Arch, FrameSize, PagingLevel};
+use core::mem;
+use sel4_sys::*;
+use vspace::CachePolicy;
+
+/// seL4_X86_WriteBack
+const X86_WRITE_BACK: seL4_X86_VMAttributes = 0;
+/// seL4_X86_CacheDisabled
+const X86_CACHE_DISABLED: seL4_X86_VMAttributes = 2;
+/// seL4_X86_WriteCombining
+const X86_WRITE_COMBINING: seL4_X86_VMAttributes = 4;
+
+pub struct X86_64;
+
+impl Arch for X86_64 {
+    type VMAttributes = seL4_X86_VMAttributes;
+
+    const PAGE_OBJECT: seL4_ObjectType = _object_seL4_X86_4K;
+    const PAGE_BITS: usize = seL4_PageBits as _;
+
+    const FRAME_SIZES: &'static [FrameSize] = &[
+        FrameSize {
+            obj_type: _object_seL4_X86_4K,
+            size_bits: 12,
+        },
+        FrameSize {
+            obj_type: _object_seL4_X86_LargePageObject,
+            size_bits: 21,
+        },
+        FrameSize {
+            obj_type: _mode_object_seL4_X86_HugePageObject,
+            size_bits: 30,
+        },
+    ];
+
+    const PAGING_LEVELS: &'static [PagingLevel] = &[
+        PagingLevel {
+            obj_type: _mode_object_seL4_X86_PDPTObject,
+            size_bits: seL4_PDPTBits as _,
+            coverage_bits: 39,
+        },
+        PagingLevel {
+            obj_type: _object_seL4_X86_PageDirectoryObject,
+            size_bits: seL4_PageDirBits as _,
+            coverage_bits: 30,
+        },
+        PagingLevel {
+            obj_type: _object_seL4_X86_PageTableObject,
+            size_bits: seL4_PageTableBits as _,
+            coverage_bits: 21,
+        },
+    ];
+
+    /// well above the root task image, the kernel lives in the upper half
+    const VSPACE_START: seL4_Word = 0x1000_0000;
+    const VSPACE_END: seL4_Word = 0x0000_7FFF_FFFF_F000;
+
+    const UNKNOWN_SYSCALL_IP_MR: usize = 15;
+    const UNKNOWN_SYSCALL_NUMBER_MR: usize = 18;
+
+    const BADGE_BITS: usize = 64;
+
+    /// seL4 does not expose an execute-never attribute on x86
+    fn vm_attributes(cache: CachePolicy, _executable: bool) -> seL4_X86_VMAttributes {
+        match cache {
+            CachePolicy::Cached => X86_WRITE_BACK,
+            CachePolicy::Uncached => X86_CACHE_DISABLED,
+            CachePolicy::WriteCombine => X86_WRITE_COMBINING,
+        }
+    }
+
+    unsafe fn page_map(
+        frame_cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        rights: seL4_CapRights_t,
+        attribs: seL4_X86_VMAttributes,
+    ) -> seL4_Error {
+        seL4_X86_Page_Map(frame_cap, vspace_cap, vaddr, rights, attribs)
+    }
+
+    unsafe fn page_unmap(frame_cap: seL4_CPtr) -> seL4_Error {
+        seL4_X86_Page_Unmap(frame_cap)
+    }
+
+    unsafe fn paging_structure_map(
+        level: usize,
+        cap: seL4_CPtr,
+        vspace_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        attribs: seL4_X86_VMAttributes,
+    ) -> seL4_Error {
+        match level {
+            0 => seL4_X86_PDPT_Map(cap, vspace_cap, vaddr, attribs),
+            1 => seL4_X86_PageDirectory_Map(cap, vspace_cap, vaddr, attribs),
+            2 => seL4_X86_PageTable_Map(cap, vspace_cap, vaddr, attribs),
+            _ => panic!("Invalid paging level {}", level),
+        }
+    }
+
+    fn init_user_context(
+        regs: &mut seL4_UserContext,
+        pc: seL4_Word,
+        sp: seL4_Word,
+        args: [seL4_Word; 3],
+    ) {
+        regs.rip = pc;
+        // the ABI expects the stack to be misaligned by the return address
+        // pushed by a call instruction
+        regs.rsp = sp - mem::size_of::<seL4_Word>() as seL4_Word;
+        regs.rdi = args[0];
+        regs.rsi = args[1];
+        regs.rdx = args[2];
+    }
+}
@@ -0,0 +1,178 @@
+//! Badges are split into namespaces by the top two bits the kernel keeps,
+//! so the kind of any received badge is known from the badge alone
+//!
+//! ```text
+//! | tag (2 bits) | value (BADGE_BITS - 2 bits) |
+//! ```
+//!
+//! Fault and IPC badges carry a counter in the value bits. Notification
+//! badges carry a single value bit each, the kernel ORs the badges of
+//! pending signals together so they can still be told apart.
+
+use alloc::vec::Vec;
+use arch::{Arch, CurrentArch};
+use core::mem;
+use error::Error;
+use sel4_sys::seL4_Word;
+
+const WORD_BITS: usize = mem::size_of::<seL4_Word>() * 8;
+const TAG_SHIFT: usize = CurrentArch::BADGE_BITS - 2;
+const VALUE_MASK: seL4_Word = (1 << TAG_SHIFT) - 1;
+const BADGE_MASK: seL4_Word = !0 >> (WORD_BITS - CurrentArch::BADGE_BITS);
+
+const FAULT_TAG: seL4_Word = 1;
+const IPC_TAG: seL4_Word = 2;
+const NOTIFICATION_TAG: seL4_Word = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BadgeKind {
+    /// Faults and exits of a thread, delivered on a fault endpoint
+    Fault,
+    /// Messages to a thread's IPC endpoint
+    Ipc,
+    /// A set of notification bits
+    Notification,
+}
+
+impl BadgeKind {
+    fn tag(&self) -> seL4_Word {
+        match *self {
+            BadgeKind::Fault => FAULT_TAG,
+            BadgeKind::Ipc => IPC_TAG,
+            BadgeKind::Notification => NOTIFICATION_TAG,
+        }
+    }
+}
+
+/// Counter based namespace, freed values are reused
+struct Namespace {
+    next: seL4_Word,
+    free: Vec<seL4_Word>,
+}
+
+impl Namespace {
+    fn new() -> Namespace {
+        Namespace {
+            next: 0,
+            free: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> Option<seL4_Word> {
+        if let Some(value) = self.free.pop() {
+            return Some(value);
+        }
+
+        if self.next > VALUE_MASK {
+            return None;
+        }
+
+        self.next += 1;
+        Some(self.next - 1)
+    }
+
+    /// Returns the value to the namespace, false if it is not in use
+    fn free(&mut self, value: seL4_Word) -> bool {
+        if value >= self.next || self.free.contains(&value) {
+            return false;
+        }
+
+        self.free.push(value);
+        true
+    }
+}
+
+/// Issues unique badges from the fault, IPC and notification namespaces
+pub struct BadgeAllocator {
+    fault: Namespace,
+    ipc: Namespace,
+    /// notification value bits in use
+    notification_bits: seL4_Word,
+}
+
+impl BadgeAllocator {
+    pub fn new() -> BadgeAllocator {
+        BadgeAllocator {
+            fault: Namespace::new(),
+            ipc: Namespace::new(),
+            notification_bits: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, kind: BadgeKind) -> Result<seL4_Word, Error> {
+        let value = match kind {
+            BadgeKind::Fault => self.fault.alloc(),
+            BadgeKind::Ipc => self.ipc.alloc(),
+            BadgeKind::Notification => {
+                let free_bits = !self.notification_bits & VALUE_MASK;
+                if free_bits == 0 {
+                    None
+                } else {
+                    let bit = 1 << free_bits.trailing_zeros();
+                    self.notification_bits |= bit;
+                    Some(bit)
+                }
+            }
+        };
+
+        match value {
+            Some(value) => Ok(kind.tag() << TAG_SHIFT | value),
+            None => Err(Error::BadgesExhausted { kind }),
+        }
+    }
+
+    /// Returns the badge to its namespace, notification badges may
+    /// hold several bits. Fails for a badge that is not in use
+    pub fn free(&mut self, badge: seL4_Word) -> Result<(), Error> {
+        let value = badge & VALUE_MASK;
+
+        let freed = match classify(badge) {
+            Some(BadgeKind::Fault) => self.fault.free(value),
+            Some(BadgeKind::Ipc) => self.ipc.free(value),
+            Some(BadgeKind::Notification) => {
+                if value != 0 && self.notification_bits & value == value {
+                    self.notification_bits &= !value;
+                    true
+                } else {
+                    false
+                }
+            }
+            None => false,
+        };
+
+        if !freed {
+            return Err(Error::BadgeNotAllocated { badge });
+        }
+
+        Ok(())
+    }
+}
+
+/// Namespace of a badge issued by the BadgeAllocator, None for
+/// the unbadged 0 and badges from outside the allocator
+pub fn classify(badge: seL4_Word) -> Option<BadgeKind> {
+    if badge & !BADGE_MASK != 0 {
+        return None;
+    }
+
+    match badge >> TAG_SHIFT {
+        FAULT_TAG => Some(BadgeKind::Fault),
+        IPC_TAG => Some(BadgeKind::Ipc),
+        NOTIFICATION_TAG => Some(BadgeKind::Notification),
+        _ => None,
+    }
+}
+
+/// Value bits of the badge, the counter of fault and IPC badges
+pub fn value(badge: seL4_Word) -> seL4_Word {
+    badge & VALUE_MASK
+}
+
+/// Checks the badge survives minting, the kernel drops bits above BADGE_BITS
+pub fn validate(badge: seL4_Word) -> Result<(), Error> {
+    if badge & !BADGE_MASK != 0 {
+        return Err(Error::InvalidBadge { badge });
+    }
+
+    Ok(())
+}
@@ -13,10 +13,6 @@ extern crate fel4_test_project;
 extern crate sel4_sys;
 extern crate wee_alloc;
 
-#[path = "../macros.rs"]
-#[macro_use]
-mod macros;
-
 use core::alloc::Layout;
 use core::intrinsics;
 use core::panic::PanicInfo;
@@ -168,28 +164,7 @@ pub extern "C" fn oom(_layout: Layout) -> ! {
 fn main() {
     let bootinfo = unsafe { &*BOOTINFO };
 
-    let mut init_sys = fel4_test_project::InitSystem::new(bootinfo);
-
-    let global_fault_ep = init_sys.init();
-
-    // wait on the fault endpoint if given one
-    loop {
-        if let Some(fault_ep) = global_fault_ep {
-            let mut badge: seL4_Word = 0;
-
-            let _msg_tag = unsafe { seL4_Wait(fault_ep, &mut badge) };
-
-            if init_sys.is_fault(badge) {
-                init_sys.handle_fault(badge);
-            } else {
-                debug_println!("\nroot-task got notification - badge 0x{:X}", badge);
-            }
-        } else {
-            unsafe {
-                seL4_Yield();
-            }
-        }
-    }
+    fel4_test_project::run(bootinfo);
 }
 
 global_asm!(
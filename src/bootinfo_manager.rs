@@ -1,14 +1,23 @@
+use arch::{self, Arch, CurrentArch};
+use core::{cmp, mem};
+use error::{Error, KernelError, Operation};
 use sel4_sys::*;
+use slot_allocator::SlotAllocator;
+use untyped_allocator::{UntypedAllocator, CHUNK_SIZE_BITS};
+use vspace::{CachePolicy, MapFlags, Region, VSpaceManager};
 
 #[cfg(feature = "KernelPrinting")]
 use sel4_sys::DebugOutHandle;
 
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
 pub struct BootInfoManager {
     bootinfo: &'static seL4_BootInfo,
-    empty_used: seL4_CPtr,
+    slots: SlotAllocator,
+    untypeds: UntypedAllocator,
+    vspace: VSpaceManager,
     cspace_cap: seL4_CPtr,
     pd_cap: seL4_CPtr,
-    page_table_cap: seL4_CPtr,
 }
 
 impl BootInfoManager {
@@ -17,136 +26,566 @@ impl BootInfoManager {
 
         BootInfoManager {
             bootinfo,
-            empty_used: 1 as _, // page_table_cap reserves the first empty slot
+            slots: SlotAllocator::new(bootinfo.empty),
+            untypeds: UntypedAllocator::new(bootinfo),
+            vspace: VSpaceManager::new(),
             cspace_cap: seL4_CapInitThreadCNode,
             pd_cap: seL4_CapInitThreadVSpace,
-            page_table_cap: bootinfo.empty.start,
         }
     }
 
-    pub fn io_map(
-        &mut self,
-        untyped_cap: seL4_CPtr,
-        paddr: seL4_Word,
-        vaddr: seL4_Word,
-        size_bits: usize,
-    ) -> seL4_Error {
+    /// Maps the device memory [paddr, paddr + size) at vaddr using the
+    /// fewest frames, i.e. the largest frame aligned at both addresses each step.
+    /// size is rounded up to whole pages, i.e. for a device with a few registers.
+    /// The range is reserved in the VSpace, release it with unmap_region and
+    /// free_vaddr. On failure the frames mapped so far are unmapped again
+    pub fn io_map(&mut self, paddr: seL4_Word, vaddr: seL4_Word, size: usize) -> Result<(), Error> {
         debug_println!(
-            "io_map: mapping frame paddr 0x{:X} -> vaddr 0x{:X} - size = {}",
+            "io_map: mapping paddr 0x{:X} -> vaddr 0x{:X} - size = {}",
             paddr,
             vaddr,
-            (1 << size_bits)
+            size
         );
 
-        if let Some(frame_cap) = self.get_frame_cap(paddr, size_bits) {
-            self.map_paddr(untyped_cap, frame_cap, vaddr)
-        } else {
-            panic!("Failed to get frame cap");
+        let page_mask: seL4_Word = (1 << CurrentArch::PAGE_BITS) - 1;
+        if (paddr | vaddr) & page_mask != 0 {
+            return Err(Error::Unaligned {
+                addr: if paddr & page_mask != 0 { paddr } else { vaddr },
+                size_bits: CurrentArch::PAGE_BITS,
+            });
         }
+
+        let size = (size + page_mask as usize) & !(page_mask as usize);
+        self.vspace.reserve(vaddr, size)?;
+
+        let mut offset = 0;
+        if let Err(e) = self.io_map_frames(paddr, vaddr, size, &mut offset) {
+            self.unmap_region(vaddr, offset)?;
+            self.vspace.free(vaddr);
+            return Err(e);
+        }
+
+        Ok(())
     }
 
-    pub fn map_paddr(
-        &self,
-        untyped_cap: seL4_CPtr,
-        frame_cap: seL4_CPtr,
+    /// Maps the frames of io_map, offset is the size of the part mapped
+    fn io_map_frames(
+        &mut self,
+        paddr: seL4_Word,
         vaddr: seL4_Word,
-    ) -> seL4_Error {
-        // memory mapped IO device region, no cache attributes
-        let cache_attribs: seL4_ARM_VMAttributes = 0;
-
-        // cap rights grant, read, write
-        let cap_grant = 0;
-        let cap_read = 1;
-        let cap_write = 1;
-
-        let map_err: seL4_Error = unsafe {
-            seL4_ARM_Page_Map(
-                frame_cap,
-                self.pd_cap,
-                vaddr,
-                seL4_CapRights_new(cap_grant, cap_read, cap_write),
-                cache_attribs,
-            )
-        };
-
-        // TODO - make this better
-        if map_err != 0 {
-            let err = self.untyped_retype_root(
-                untyped_cap,
-                _object_seL4_ARM_PageTableObject,
-                seL4_PageTableBits as usize,
-                self.page_table_cap,
-            );
+        size: usize,
+        offset: &mut usize,
+    ) -> Result<(), Error> {
+        while *offset < size {
+            let frame_paddr = paddr + *offset as seL4_Word;
+            let frame_vaddr = vaddr + *offset as seL4_Word;
+            let frame = arch::largest_frame_size(Some(frame_paddr), frame_vaddr, size - *offset);
 
-            if err != 0 {
-                return err;
+            let frame_cap = self.get_frame_cap(frame_paddr, frame.size_bits)?;
+            if let Err(e) =
+                self.map_paddr(frame_cap, frame_vaddr, frame.size_bits, MapFlags::device())
+            {
+                self.delete_cap(frame_cap)?;
+                return Err(e);
             }
 
-            let err: seL4_Error = unsafe {
-                seL4_ARM_PageTable_Map(self.page_table_cap, self.pd_cap, vaddr, cache_attribs)
+            *offset += 1 << frame.size_bits;
+        }
+
+        Ok(())
+    }
+
+    /// Backs [vaddr, vaddr + size) with newly allocated RAM frames, using the
+    /// largest frames the alignment allows and smaller ones if untyped memory
+    /// for a large frame is not available. On failure the frames mapped so
+    /// far are unmapped again
+    pub fn map_region(
+        &mut self,
+        vaddr: seL4_Word,
+        size: usize,
+        flags: MapFlags,
+    ) -> Result<(), Error> {
+        let page_mask: seL4_Word = (1 << CurrentArch::PAGE_BITS) - 1;
+        if vaddr & page_mask != 0 {
+            return Err(Error::Unaligned {
+                addr: vaddr,
+                size_bits: CurrentArch::PAGE_BITS,
+            });
+        }
+
+        let mut offset = 0;
+        if let Err(e) = self.map_region_frames(vaddr, size, flags, &mut offset) {
+            self.unmap_region(vaddr, offset)?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Maps the frames of map_region, offset is the size of the part mapped
+    fn map_region_frames(
+        &mut self,
+        vaddr: seL4_Word,
+        size: usize,
+        flags: MapFlags,
+        offset: &mut usize,
+    ) -> Result<(), Error> {
+        while *offset < size {
+            let frame_vaddr = vaddr + *offset as seL4_Word;
+            let mut frame = arch::largest_frame_size(None, frame_vaddr, size - *offset);
+
+            let frame_cap = loop {
+                match self.alloc_object(frame.obj_type, frame.size_bits) {
+                    Err(Error::NoUntyped { .. }) if frame.size_bits > CurrentArch::PAGE_BITS => {
+                        frame = arch::smaller_frame_size(frame.size_bits)
+                            .expect("small page is the smallest frame size");
+                    }
+                    result => break result?,
+                }
             };
 
-            if err != 0 {
-                return err;
+            if let Err(e) = self.map_paddr(frame_cap, frame_vaddr, frame.size_bits, flags) {
+                self.delete_cap(frame_cap)?;
+                return Err(e);
             }
 
-            let err: seL4_Error = unsafe {
-                seL4_ARM_Page_Map(
-                    frame_cap,
-                    self.pd_cap,
-                    vaddr,
-                    seL4_CapRights_new(cap_grant, cap_read, cap_write),
-                    cache_attribs,
-                )
+            *offset += 1 << frame.size_bits;
+        }
+
+        Ok(())
+    }
+
+    /// Unmaps the frames mapped within [vaddr, vaddr + size) and deletes
+    /// their caps, the frames are not returned to untyped memory
+    pub fn unmap_region(&mut self, vaddr: seL4_Word, size: usize) -> Result<(), Error> {
+        let end = vaddr + size as seL4_Word;
+        let mut frame_vaddr = vaddr;
+
+        while frame_vaddr < end {
+            let (start, mapping) = match self.vspace.find_mapping(frame_vaddr) {
+                Some(found) => found,
+                None => {
+                    frame_vaddr += 1 << CurrentArch::PAGE_BITS;
+                    continue;
+                }
             };
 
-            if err != 0 {
-                return err;
+            let err: seL4_Error = unsafe { CurrentArch::page_unmap(mapping.frame_cap) };
+            Error::check(Operation::PageUnmap, mapping.frame_cap, err)?;
+
+            self.vspace.remove_mapping(start);
+            self.delete_cap(mapping.frame_cap)?;
+
+            frame_vaddr = start + (1 << mapping.size_bits);
+        }
+
+        Ok(())
+    }
+
+    /// Maps the frame of 2^size_bits bytes at vaddr, fails if a frame
+    /// is already mapped there
+    pub fn map_paddr(
+        &mut self,
+        frame_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        size_bits: usize,
+        flags: MapFlags,
+    ) -> Result<(), Error> {
+        self.vspace.add_mapping(vaddr, size_bits, frame_cap)?;
+
+        let result = self.map_frame(frame_cap, vaddr, size_bits, flags);
+        if result.is_err() {
+            self.vspace.remove_mapping(vaddr);
+        }
+
+        result
+    }
+
+    /// Reserves a free virtual address range of size bytes aligned to 2^align_bits
+    pub fn alloc_vaddr(&mut self, size: usize, align_bits: usize) -> Result<seL4_Word, Error> {
+        self.vspace.alloc(size, align_bits)
+    }
+
+    /// Reserves the fixed virtual address range [vaddr, vaddr + size)
+    pub fn reserve_vaddr(&mut self, vaddr: seL4_Word, size: usize) -> Result<(), Error> {
+        self.vspace.reserve(vaddr, size)
+    }
+
+    /// Reserves [vaddr, vaddr + size) to be backed by frames on first access,
+    /// see map_lazy_page. vaddr and size must be page aligned
+    pub fn reserve_lazy_region(
+        &mut self,
+        vaddr: seL4_Word,
+        size: usize,
+        flags: MapFlags,
+    ) -> Result<(), Error> {
+        check_page_aligned(vaddr, size)?;
+        self.vspace.reserve(vaddr, size)?;
+        self.vspace.add_lazy_region(Region::new(vaddr, size), flags);
+        Ok(())
+    }
+
+    /// Reserves a free page aligned region of size bytes to be backed by
+    /// frames on first access, returns its start. size must be page aligned
+    pub fn alloc_lazy_region(&mut self, size: usize, flags: MapFlags) -> Result<seL4_Word, Error> {
+        check_page_aligned(0, size)?;
+        let vaddr = self.vspace.alloc(size, CurrentArch::PAGE_BITS)?;
+        self.vspace.add_lazy_region(Region::new(vaddr, size), flags);
+        Ok(vaddr)
+    }
+
+    /// Maps a new frame at the page containing vaddr if it lies in a lazy
+    /// region and is not mapped yet, returns false otherwise
+    pub fn map_lazy_page(&mut self, vaddr: seL4_Word) -> Result<bool, Error> {
+        let flags = match self.vspace.find_lazy_region(vaddr) {
+            Some((_, flags)) => flags,
+            None => return Ok(false),
+        };
+
+        if self.vspace.is_mapped(vaddr) {
+            return Ok(false);
+        }
+
+        let page_vaddr = vaddr & !((1 << CurrentArch::PAGE_BITS) - 1);
+        let frame_cap = self.alloc_object(CurrentArch::PAGE_OBJECT, CurrentArch::PAGE_BITS)?;
+
+        if let Err(e) = self.map_paddr(frame_cap, page_vaddr, CurrentArch::PAGE_BITS, flags) {
+            self.delete_cap(frame_cap)?;
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    /// Releases the reserved virtual address range starting at vaddr, the
+    /// pages mapped in a lazy region are unmapped first
+    pub fn free_vaddr(&mut self, vaddr: seL4_Word) -> Result<(), Error> {
+        if let Some((region, _)) = self.vspace.find_lazy_region(vaddr) {
+            if region.start == vaddr {
+                self.unmap_region(region.start, region.size())?;
             }
         }
-        0
+
+        self.vspace.free(vaddr);
+        Ok(())
     }
 
-    pub fn get_frame_cap(&mut self, paddr: seL4_Word, size_bits: usize) -> Option<seL4_CPtr> {
-        if let Some(dest_slot_cap) = self.get_next_free_cap_slot() {
-            if let Some(untyped_cap) = self.get_untyped(Some(paddr), 1 << size_bits) {
-                let err = self.untyped_retype_root(
-                    untyped_cap,
-                    _object_seL4_ARM_SmallPageObject,
-                    size_bits,
-                    dest_slot_cap,
-                );
+    fn map_frame(
+        &mut self,
+        frame_cap: seL4_CPtr,
+        vaddr: seL4_Word,
+        size_bits: usize,
+        flags: MapFlags,
+    ) -> Result<(), Error> {
+        let attribs = CurrentArch::vm_attributes(flags.cache, flags.executable);
+        let rights = flags.rights.cap_rights();
 
-                if err == 0 {
-                    return Some(dest_slot_cap);
-                }
+        let err: seL4_Error =
+            unsafe { CurrentArch::page_map(frame_cap, self.pd_cap, vaddr, rights, attribs) };
+
+        match Error::check(Operation::PageMap, frame_cap, err) {
+            // a paging structure covering vaddr is missing, create them below and retry
+            Err(ref e) if e.kernel_error() == Some(KernelError::FailedLookup) => (),
+            result => return result,
+        }
+
+        // paging structures are mapped with the default (cached) attributes
+        let paging_attribs = CurrentArch::vm_attributes(CachePolicy::Cached, true);
+
+        // large frames are mapped directly into a higher level structure,
+        // only the levels covering more than the frame are needed
+        for (level, paging_level) in CurrentArch::PAGING_LEVELS.iter().enumerate() {
+            if paging_level.coverage_bits > size_bits
+                && !self.vspace.has_paging_structure(level, vaddr)
+            {
+                self.map_paging_structure(level, vaddr, paging_attribs)?;
             }
         }
 
-        None
+        let err: seL4_Error =
+            unsafe { CurrentArch::page_map(frame_cap, self.pd_cap, vaddr, rights, attribs) };
+        Error::check(Operation::PageMap, frame_cap, err)
     }
 
-    pub fn get_untyped(&self, paddr: Option<seL4_Word>, size_bytes: usize) -> Option<seL4_CPtr> {
-        for i in self.bootinfo.untyped.start..self.bootinfo.untyped.end {
-            let idx: usize = (i - self.bootinfo.untyped.start) as usize;
-            if (1 << self.bootinfo.untypedList[idx].sizeBits) as usize >= size_bytes {
-                if let Some(paddr) = paddr {
-                    if self.bootinfo.untypedList[idx].paddr == paddr {
-                        return Some(i);
-                    }
+    /// Creates and maps a paging structure of PAGING_LEVELS[level] covering vaddr,
+    /// structures the kernel already created (i.e. for the root task image) are
+    /// only recorded
+    fn map_paging_structure(
+        &mut self,
+        level: usize,
+        vaddr: seL4_Word,
+        attribs: <CurrentArch as Arch>::VMAttributes,
+    ) -> Result<(), Error> {
+        let paging_level = CurrentArch::PAGING_LEVELS[level];
+
+        let cap = self.alloc_object(paging_level.obj_type, paging_level.size_bits)?;
+
+        let err: seL4_Error =
+            unsafe { CurrentArch::paging_structure_map(level, cap, self.pd_cap, vaddr, attribs) };
+
+        match Error::check(Operation::PageTableMap, cap, err) {
+            Ok(()) => {
+                self.vspace.add_paging_structure(level, vaddr, Some(cap));
+                Ok(())
+            }
+            Err(e) => {
+                self.delete_cap(cap)?;
+
+                if e.kernel_error() == Some(KernelError::DeleteFirst) {
+                    self.vspace.add_paging_structure(level, vaddr, None);
+                    Ok(())
                 } else {
-                    return Some(i);
+                    Err(e)
                 }
             }
         }
-        None
     }
 
-    /// TODO - error handling
-    pub fn get_next_free_cap_slot(&mut self) -> Option<seL4_CPtr> {
-        let offset = self.empty_used;
-        self.empty_used += 1;
-        Some(self.bootinfo.empty.start + offset)
+    /// Creates a frame cap for the device memory at paddr of 2^size_bits bytes,
+    /// which must be a frame size of the architecture. The frame is retyped
+    /// from the device untyped containing paddr, any unused space in front of it
+    /// is split off into smaller untypeds so it can still be mapped later
+    pub fn get_frame_cap(
+        &mut self,
+        paddr: seL4_Word,
+        size_bits: usize,
+    ) -> Result<seL4_CPtr, Error> {
+        let frame = arch::frame_size(size_bits).ok_or(Error::UnsupportedFrameSize { size_bits })?;
+
+        if paddr & ((1 << size_bits) - 1) != 0 {
+            return Err(Error::Unaligned {
+                addr: paddr,
+                size_bits,
+            });
+        }
+
+        let untyped_idx =
+            self.untypeds
+                .find_device(paddr, 1 << size_bits)
+                .ok_or(Error::NoDeviceUntyped {
+                    paddr,
+                    size_bytes: 1 << size_bits,
+                })?;
+
+        let next_paddr = self.untypeds.get(untyped_idx).next_paddr(size_bits);
+        if next_paddr > paddr {
+            return Err(Error::PaddrInUse { paddr });
+        }
+
+        if next_paddr < paddr {
+            self.split_device_gap(untyped_idx, paddr)?;
+        }
+
+        let frame_cap = self.alloc_slot()?;
+
+        if let Err(e) = self.retype_from(untyped_idx, frame.obj_type, size_bits, frame_cap, 1) {
+            self.free_slot(frame_cap);
+            return Err(e);
+        }
+
+        Ok(frame_cap)
+    }
+
+    /// Retypes the unused space of the device untyped below end_paddr into
+    /// the largest aligned child untypeds that fit, afterwards the untyped's
+    /// next object is placed at end_paddr
+    fn split_device_gap(&mut self, untyped_idx: usize, end_paddr: seL4_Word) -> Result<(), Error> {
+        let min_bits = seL4_MinUntypedBits as usize;
+
+        loop {
+            let gap_paddr = self.untypeds.get(untyped_idx).next_paddr(min_bits);
+            if gap_paddr >= end_paddr {
+                return Ok(());
+            }
+
+            // largest child that is aligned at gap_paddr and ends at or before end_paddr
+            let gap_size = (end_paddr - gap_paddr) as usize;
+            let align_bits = gap_paddr.trailing_zeros() as usize;
+            let size_fit_bits = WORD_BITS - 1 - gap_size.leading_zeros() as usize;
+            let child_bits = cmp::min(align_bits, size_fit_bits);
+
+            if child_bits < min_bits {
+                return Err(Error::Unaligned {
+                    addr: gap_paddr,
+                    size_bits: min_bits,
+                });
+            }
+
+            let child_cap = self.alloc_slot()?;
+
+            let child_paddr = match self.retype_from(
+                untyped_idx,
+                api_object_seL4_UntypedObject,
+                child_bits,
+                child_cap,
+                1,
+            ) {
+                Ok(paddr) => paddr,
+                Err(e) => {
+                    self.free_slot(child_cap);
+                    return Err(e);
+                }
+            };
+
+            self.untypeds
+                .add_child(child_cap, child_paddr, child_bits, true);
+        }
+    }
+
+    /// Creates a kernel object of the given type and size from untyped memory,
+    /// returns a cap to the new object
+    pub fn alloc_object(
+        &mut self,
+        obj_type: seL4_ObjectType,
+        size_bits: usize,
+    ) -> Result<seL4_CPtr, Error> {
+        let obj_cap = self.alloc_slot()?;
+
+        if let Err(e) = self.retype_into(obj_type, size_bits, obj_cap) {
+            self.free_slot(obj_cap);
+            return Err(e);
+        }
+
+        Ok(obj_cap)
+    }
+
+    /// Creates `num_objects` kernel objects of the given type and size,
+    /// returns the first of the contiguous slots holding the new caps
+    pub fn alloc_objects(
+        &mut self,
+        obj_type: seL4_ObjectType,
+        size_bits: usize,
+        num_objects: usize,
+    ) -> Result<seL4_CPtr, Error> {
+        let first_cap = self.alloc_slot_range(num_objects)?;
+
+        let result = self
+            .find_untyped(size_bits, num_objects)
+            .and_then(|idx| self.retype_from(idx, obj_type, size_bits, first_cap, num_objects));
+
+        if let Err(e) = result {
+            self.free_slot_range(first_cap, num_objects);
+            return Err(e);
+        }
+
+        Ok(first_cap)
+    }
+
+    /// Number of RAM untyped bytes not yet retyped into objects
+    pub fn untyped_free_bytes(&self) -> usize {
+        self.untypeds.free_bytes()
+    }
+
+    /// Returns the smallest device untyped containing the physical address range
+    pub fn get_device_untyped(&self, paddr: seL4_Word, size_bytes: usize) -> Option<seL4_CPtr> {
+        self.untypeds
+            .find_device(paddr, size_bytes)
+            .map(|idx| self.untypeds.get(idx).cap)
+    }
+
+    /// Retypes a single object into an already allocated empty slot
+    fn retype_into(
+        &mut self,
+        obj_type: seL4_ObjectType,
+        size_bits: usize,
+        slot_cap: seL4_CPtr,
+    ) -> Result<(), Error> {
+        let untyped_idx = self.find_untyped(size_bits, 1)?;
+        self.retype_from(untyped_idx, obj_type, size_bits, slot_cap, 1)?;
+        Ok(())
+    }
+
+    /// Index of an untyped record with room for the objects, small objects
+    /// are served from chunks split off of large untypeds
+    fn find_untyped(&mut self, size_bits: usize, num_objects: usize) -> Result<usize, Error> {
+        let untyped_idx = self
+            .untypeds
+            .find(size_bits, num_objects)
+            .ok_or(Error::NoUntyped {
+                size_bytes: num_objects << size_bits,
+            })?;
+
+        if !self
+            .untypeds
+            .should_split(untyped_idx, size_bits, num_objects)
+        {
+            return Ok(untyped_idx);
+        }
+
+        let chunk_cap = self.alloc_slot()?;
+
+        let chunk_paddr = match self.retype_from(
+            untyped_idx,
+            api_object_seL4_UntypedObject,
+            CHUNK_SIZE_BITS,
+            chunk_cap,
+            1,
+        ) {
+            Ok(paddr) => paddr,
+            Err(e) => {
+                self.free_slot(chunk_cap);
+                return Err(e);
+            }
+        };
+
+        let is_device = self.untypeds.get(untyped_idx).is_device;
+
+        Ok(self
+            .untypeds
+            .add_child(chunk_cap, chunk_paddr, CHUNK_SIZE_BITS, is_device))
+    }
+
+    /// Retypes objects from the untyped record and advances its watermark
+    /// on success, returns the physical address of the first object
+    fn retype_from(
+        &mut self,
+        untyped_idx: usize,
+        obj_type: seL4_ObjectType,
+        size_bits: usize,
+        first_slot_cap: seL4_CPtr,
+        num_objects: usize,
+    ) -> Result<seL4_Word, Error> {
+        let untyped_cap = self.untypeds.get(untyped_idx).cap;
+
+        self.untyped_retype_root_range(
+            untyped_cap,
+            obj_type,
+            size_bits,
+            first_slot_cap,
+            num_objects,
+        )?;
+
+        Ok(self.untypeds.consume(untyped_idx, size_bits, num_objects))
+    }
+
+    pub fn alloc_slot(&mut self) -> Result<seL4_CPtr, Error> {
+        self.slots.alloc()
+    }
+
+    /// Allocates `count` contiguous slots, returns the first slot
+    pub fn alloc_slot_range(&mut self, count: usize) -> Result<seL4_CPtr, Error> {
+        self.slots.alloc_range(count)
+    }
+
+    /// Returns an empty slot to the allocator, use delete_cap
+    /// for slots that still hold a cap
+    pub fn free_slot(&mut self, slot_cap: seL4_CPtr) {
+        self.slots.free(slot_cap)
+    }
+
+    pub fn free_slot_range(&mut self, first_slot_cap: seL4_CPtr, count: usize) {
+        self.slots.free_range(first_slot_cap, count)
+    }
+
+    /// Deletes the cap in the slot and returns the slot to the allocator
+    pub fn delete_cap(&mut self, slot_cap: seL4_CPtr) -> Result<(), Error> {
+        let err: seL4_Error =
+            unsafe { seL4_CNode_Delete(self.cspace_cap, slot_cap, seL4_WordBits as _) };
+        Error::check(Operation::CNodeDelete, slot_cap, err)?;
+
+        self.slots.free(slot_cap);
+
+        Ok(())
     }
 
     /// TODO - maybe use a cspacepath_t object here?
@@ -160,8 +599,22 @@ impl BootInfoManager {
         obj_type: seL4_ObjectType,
         size_bits: usize,
         slot_cap: seL4_CPtr,
-    ) -> seL4_Error {
-        unsafe {
+    ) -> Result<(), Error> {
+        self.untyped_retype_root_range(untyped_cap, obj_type, size_bits, slot_cap, 1)
+    }
+
+    /// Retypes an untyped object into `num_objects` objects of the specified type
+    /// and size, storing the caps in the contiguous slots starting at first_slot_cap,
+    /// see alloc_slot_range
+    pub fn untyped_retype_root_range(
+        &self,
+        untyped_cap: seL4_CPtr,
+        obj_type: seL4_ObjectType,
+        size_bits: usize,
+        first_slot_cap: seL4_CPtr,
+        num_objects: usize,
+    ) -> Result<(), Error> {
+        let err: seL4_Error = unsafe {
             seL4_Untyped_Retype(
                 untyped_cap,
                 obj_type,
@@ -169,10 +622,11 @@ impl BootInfoManager {
                 self.cspace_cap,
                 self.cspace_cap,
                 seL4_WordBits.into(),
-                slot_cap,
-                1,
+                first_slot_cap,
+                num_objects as _,
             )
-        }
+        };
+        Error::check(Operation::UntypedRetype, untyped_cap, err)
     }
 
     pub fn debug_print_bootinfo(&self) {
@@ -214,3 +668,22 @@ impl BootInfoManager {
         }
     }
 }
+
+/// Checks vaddr and size are multiples of the page size
+fn check_page_aligned(vaddr: seL4_Word, size: usize) -> Result<(), Error> {
+    let page_mask: seL4_Word = (1 << CurrentArch::PAGE_BITS) - 1;
+    let unaligned = vaddr | size as seL4_Word;
+
+    if unaligned & page_mask != 0 {
+        return Err(Error::Unaligned {
+            addr: if vaddr & page_mask != 0 {
+                vaddr
+            } else {
+                size as seL4_Word
+            },
+            size_bits: CurrentArch::PAGE_BITS,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use badge::BadgeKind;
+use core::fmt;
+use fault::Fault;
+use ipc::ServerError;
+use sel4_sys::{seL4_CPtr, seL4_Error, seL4_Word};
+
+/// Errors returned by the seL4 kernel, see libsel4 `errors.h`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KernelError {
+    InvalidArgument,
+    InvalidCapability,
+    IllegalOperation,
+    RangeError,
+    AlignmentError,
+    FailedLookup,
+    TruncatedMessage,
+    DeleteFirst,
+    RevokeFirst,
+    NotEnoughMemory,
+    /// An error code this crate does not know about
+    Unknown(seL4_Error),
+}
+
+impl KernelError {
+    /// Returns None for seL4_NoError
+    pub fn from_code(code: seL4_Error) -> Option<KernelError> {
+        match code {
+            0 => None,
+            1 => Some(KernelError::InvalidArgument),
+            2 => Some(KernelError::InvalidCapability),
+            3 => Some(KernelError::IllegalOperation),
+            4 => Some(KernelError::RangeError),
+            5 => Some(KernelError::AlignmentError),
+            6 => Some(KernelError::FailedLookup),
+            7 => Some(KernelError::TruncatedMessage),
+            8 => Some(KernelError::DeleteFirst),
+            9 => Some(KernelError::RevokeFirst),
+            10 => Some(KernelError::NotEnoughMemory),
+            _ => Some(KernelError::Unknown(code)),
+        }
+    }
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KernelError::InvalidArgument => write!(f, "seL4_InvalidArgument"),
+            KernelError::InvalidCapability => write!(f, "seL4_InvalidCapability"),
+            KernelError::IllegalOperation => write!(f, "seL4_IllegalOperation"),
+            KernelError::RangeError => write!(f, "seL4_RangeError"),
+            KernelError::AlignmentError => write!(f, "seL4_AlignmentError"),
+            KernelError::FailedLookup => write!(f, "seL4_FailedLookup"),
+            KernelError::TruncatedMessage => write!(f, "seL4_TruncatedMessage"),
+            KernelError::DeleteFirst => write!(f, "seL4_DeleteFirst"),
+            KernelError::RevokeFirst => write!(f, "seL4_RevokeFirst"),
+            KernelError::NotEnoughMemory => write!(f, "seL4_NotEnoughMemory"),
+            KernelError::Unknown(code) => write!(f, "unknown seL4 error {}", code),
+        }
+    }
+}
+
+/// The kernel invocation that failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operation {
+    UntypedRetype,
+    PageMap,
+    PageUnmap,
+    PageTableMap,
+    CNodeMint,
+    CNodeDelete,
+    CNodeSaveCaller,
+    TCBConfigure,
+    TCBWriteRegisters,
+    TCBSetPriority,
+    TCBSetMCPriority,
+    TCBResume,
+    TCBSuspend,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Operation::UntypedRetype => "seL4_Untyped_Retype",
+            Operation::PageMap => "seL4_ARCH_Page_Map",
+            Operation::PageUnmap => "seL4_ARCH_Page_Unmap",
+            Operation::PageTableMap => "seL4_ARCH_PageTable_Map",
+            Operation::CNodeMint => "seL4_CNode_Mint",
+            Operation::CNodeDelete => "seL4_CNode_Delete",
+            Operation::CNodeSaveCaller => "seL4_CNode_SaveCaller",
+            Operation::TCBConfigure => "seL4_TCB_Configure",
+            Operation::TCBWriteRegisters => "seL4_TCB_WriteRegisters",
+            Operation::TCBSetPriority => "seL4_TCB_SetPriority",
+            Operation::TCBSetMCPriority => "seL4_TCB_SetMCPriority",
+            Operation::TCBResume => "seL4_TCB_Resume",
+            Operation::TCBSuspend => "seL4_TCB_Suspend",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A kernel invocation on `cap` returned an error
+    Kernel {
+        op: Operation,
+        cap: seL4_CPtr,
+        err: KernelError,
+    },
+    /// No untyped large enough for the requested number of bytes
+    NoUntyped { size_bytes: usize },
+    /// No device untyped covers the physical address range
+    NoDeviceUntyped { paddr: seL4_Word, size_bytes: usize },
+    /// The physical address range was already retyped
+    PaddrInUse { paddr: seL4_Word },
+    /// The address is not aligned to 2^size_bits bytes
+    Unaligned { addr: seL4_Word, size_bits: usize },
+    /// The virtual address range overlaps a reserved region or mapped page
+    VaddrInUse { vaddr: seL4_Word, size: usize },
+    /// The virtual address range to map is not within a reserved region
+    VaddrNotReserved { vaddr: seL4_Word, size: usize },
+    /// No free virtual address range of the requested size left
+    VSpaceExhausted { size: usize },
+    /// Not enough free (contiguous) CSpace slots
+    SlotsExhausted { count: usize },
+    /// The architecture has no frame object of 2^size_bits bytes
+    UnsupportedFrameSize { size_bits: usize },
+    /// The thread does not report its exit on the global fault endpoint,
+    /// or is gone and its exit code was dropped or taken by an earlier join
+    NotJoinable { tcb: seL4_CPtr },
+    /// Only threads started from a plain function can be restarted
+    NotRestartable { name: &'static str },
+    /// The thread was given a stack size of 0
+    EmptyStack { name: &'static str },
+    /// The joined thread was killed by its fault policy
+    ThreadKilled { fault: Fault },
+    /// The joined thread was suspended by its fault policy and never returns
+    ThreadSuspended { fault: Fault },
+    /// Every badge of the namespace is in use
+    BadgesExhausted { kind: BadgeKind },
+    /// The badge has bits set above the kernel's badge width
+    InvalidBadge { badge: seL4_Word },
+    /// A ReplyCapPool needs at least one and at most max slots
+    InvalidPoolSize { count: usize, max: usize },
+    /// The PendingReply was saved by another ReplyCapPool
+    NotInPool { slot: seL4_CPtr },
+    /// Another registered thread already has the fault badge
+    BadgeInUse { badge: seL4_Word },
+    /// The badge was not issued by the BadgeAllocator or is already freed
+    BadgeNotAllocated { badge: seL4_Word },
+    /// A message on the fault endpoint had neither a notification badge
+    /// nor the fault badge of a registered thread
+    UnexpectedBadge { badge: seL4_Word },
+    /// A message with another label than the one expected was received
+    UnexpectedLabel {
+        expected: seL4_Word,
+        label: seL4_Word,
+    },
+    /// The message has the wrong number of message registers for its label
+    MessageLength {
+        label: seL4_Word,
+        expected: usize,
+        length: usize,
+    },
+    /// The payload is larger than the IPC buffer, shared buffer or receive buffer
+    PayloadTooLarge { size: usize, capacity: usize },
+    /// More caps than seL4_MsgMaxExtraCaps to send with a message
+    TooManyCaps { count: usize },
+    /// The server could not handle the request with the label
+    Rejected {
+        error: ServerError,
+        label: seL4_Word,
+    },
+}
+
+impl Error {
+    /// Converts the seL4_Error returned by invoking `op` on `cap` into a Result
+    pub fn check(op: Operation, cap: seL4_CPtr, code: seL4_Error) -> Result<(), Error> {
+        match KernelError::from_code(code) {
+            None => Ok(()),
+            Some(err) => Err(Error::Kernel { op, cap, err }),
+        }
+    }
+
+    /// Returns the kernel error, if the error came from the kernel
+    pub fn kernel_error(&self) -> Option<KernelError> {
+        match *self {
+            Error::Kernel { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Kernel { op, cap, err } => {
+                write!(f, "{} on cap 0x{:X} failed: {}", op, cap, err)
+            }
+            Error::NoUntyped { size_bytes } => {
+                write!(f, "no untyped large enough for {} bytes", size_bytes)
+            }
+            Error::NoDeviceUntyped { paddr, size_bytes } => write!(
+                f,
+                "no device untyped covers paddr 0x{:X} - size = {}",
+                paddr, size_bytes
+            ),
+            Error::PaddrInUse { paddr } => {
+                write!(f, "paddr 0x{:X} was already retyped", paddr)
+            }
+            Error::Unaligned { addr, size_bits } => write!(
+                f,
+                "address 0x{:X} is not aligned to {} bytes",
+                addr,
+                1 << size_bits
+            ),
+            Error::VaddrInUse { vaddr, size } => write!(
+                f,
+                "vaddr 0x{:X} - size = {} overlaps an existing region or mapping",
+                vaddr, size
+            ),
+            Error::VaddrNotReserved { vaddr, size } => write!(
+                f,
+                "vaddr 0x{:X} - size = {} is not within a reserved region",
+                vaddr, size
+            ),
+            Error::VSpaceExhausted { size } => {
+                write!(f, "no free virtual address range of {} bytes left", size)
+            }
+            Error::SlotsExhausted { count } => {
+                write!(f, "no {} free CSpace slot(s) left", count)
+            }
+            Error::UnsupportedFrameSize { size_bits } => {
+                write!(f, "no frame object of {} bytes", 1 << size_bits)
+            }
+            Error::NotJoinable { tcb } => {
+                write!(f, "thread with TCB 0x{:X} cannot be joined", tcb)
+            }
+            Error::NotRestartable { name } => {
+                write!(f, "thread '{}' has no entry function to restart", name)
+            }
+            Error::EmptyStack { name } => write!(f, "thread '{}' has an empty stack", name),
+            Error::ThreadKilled { fault } => write!(f, "thread was killed after a {}", fault),
+            Error::ThreadSuspended { fault } => {
+                write!(f, "thread was suspended after a {}", fault)
+            }
+            Error::BadgesExhausted { kind } => write!(f, "no free {:?} badge left", kind),
+            Error::InvalidBadge { badge } => {
+                write!(
+                    f,
+                    "badge 0x{:X} does not fit the kernel's badge width",
+                    badge
+                )
+            }
+            Error::InvalidPoolSize { count, max } => write!(
+                f,
+                "a reply cap pool of {} slots is not between 1 and {}",
+                count, max
+            ),
+            Error::NotInPool { slot } => {
+                write!(f, "reply cap slot 0x{:X} is not in the pool", slot)
+            }
+            Error::BadgeInUse { badge } => {
+                write!(f, "fault badge 0x{:X} is already in use", badge)
+            }
+            Error::BadgeNotAllocated { badge } => write!(f, "badge 0x{:X} is not in use", badge),
+            Error::UnexpectedBadge { badge } => write!(
+                f,
+                "badge 0x{:X} is neither a thread's fault badge nor a notification badge",
+                badge
+            ),
+            Error::UnexpectedLabel { expected, label } => write!(
+                f,
+                "expected a message with label 0x{:X}, got label 0x{:X}",
+                expected, label
+            ),
+            Error::MessageLength {
+                label,
+                expected,
+                length,
+            } => write!(
+                f,
+                "message with label 0x{:X} has {} word(s), expected {}",
+                label, length, expected
+            ),
+            Error::PayloadTooLarge { size, capacity } => write!(
+                f,
+                "payload of {} bytes does not fit in {} bytes",
+                size, capacity
+            ),
+            Error::TooManyCaps { count } => {
+                write!(f, "{} caps do not fit in a single message", count)
+            }
+            Error::Rejected { error, label } => {
+                write!(f, "server rejected request 0x{:X}: {:?}", label, error)
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use arch::{Arch, CurrentArch};
+use core::fmt;
+use sel4_sys::{seL4_GetMR, seL4_MessageInfo_get_label, seL4_MessageInfo_t, seL4_Word};
+
+/// A fault message delivered to a thread's fault endpoint, decoded from
+/// the message label and registers, see libsel4 `faults.h`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Invoking or looking up a cap failed
+    CapFault {
+        ip: seL4_Word,
+        addr: seL4_Word,
+        in_recv_phase: bool,
+        lookup_failure_type: seL4_Word,
+    },
+    /// A syscall number the kernel does not know
+    UnknownSyscall { ip: seL4_Word, syscall: seL4_Word },
+    /// Architecture exception, i.e. undefined instruction or divide by zero
+    UserException {
+        ip: seL4_Word,
+        sp: seL4_Word,
+        number: seL4_Word,
+        code: seL4_Word,
+    },
+    /// Access to an unmapped address or one mapped without the needed rights
+    VMFault {
+        ip: seL4_Word,
+        addr: seL4_Word,
+        /// fault status register (or error code on x86)
+        fsr: seL4_Word,
+        /// true if the fault was an instruction fetch
+        instruction: bool,
+    },
+    /// The thread's scheduling context ran out of budget (MCS kernels only)
+    Timeout { data: seL4_Word },
+    /// A label this crate does not know about
+    Unknown { label: seL4_Word },
+}
+
+impl Fault {
+    /// Decodes the fault message just received, must be called before
+    /// the message registers are overwritten
+    pub fn decode(msg_info: seL4_MessageInfo_t) -> Fault {
+        let label = unsafe { seL4_MessageInfo_get_label(msg_info) } as seL4_Word;
+
+        match label {
+            1 => Fault::CapFault {
+                ip: get_mr(0),
+                addr: get_mr(1),
+                in_recv_phase: get_mr(2) != 0,
+                lookup_failure_type: get_mr(3),
+            },
+            2 => Fault::UnknownSyscall {
+                ip: get_mr(CurrentArch::UNKNOWN_SYSCALL_IP_MR),
+                syscall: get_mr(CurrentArch::UNKNOWN_SYSCALL_NUMBER_MR),
+            },
+            3 => Fault::UserException {
+                ip: get_mr(0),
+                sp: get_mr(1),
+                number: get_mr(3),
+                code: get_mr(4),
+            },
+            5 => Fault::VMFault {
+                ip: get_mr(0),
+                addr: get_mr(1),
+                instruction: get_mr(2) != 0,
+                fsr: get_mr(3),
+            },
+            6 => Fault::Timeout { data: get_mr(0) },
+            label => Fault::Unknown { label },
+        }
+    }
+
+    /// Instruction pointer of the faulting thread, if the message carries it
+    pub fn ip(&self) -> Option<seL4_Word> {
+        match *self {
+            Fault::CapFault { ip, .. }
+            | Fault::UnknownSyscall { ip, .. }
+            | Fault::UserException { ip, .. }
+            | Fault::VMFault { ip, .. } => Some(ip),
+            Fault::Timeout { .. } | Fault::Unknown { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::CapFault {
+                ip,
+                addr,
+                in_recv_phase,
+                lookup_failure_type,
+            } => write!(
+                f,
+                "cap fault at ip 0x{:X} - cap 0x{:X} - in receive phase = {} - lookup failure type = {}",
+                ip, addr, in_recv_phase, lookup_failure_type
+            ),
+            Fault::UnknownSyscall { ip, syscall } => {
+                write!(f, "unknown syscall {} at ip 0x{:X}", syscall as isize, ip)
+            }
+            Fault::UserException {
+                ip,
+                sp,
+                number,
+                code,
+            } => write!(
+                f,
+                "user exception {} (code {}) at ip 0x{:X} - sp 0x{:X}",
+                number, code, ip, sp
+            ),
+            Fault::VMFault {
+                ip,
+                addr,
+                fsr,
+                instruction,
+            } => write!(
+                f,
+                "vm fault on {} at addr 0x{:X} - ip 0x{:X} - fsr 0x{:X}",
+                if instruction { "instruction fetch" } else { "data access" },
+                addr,
+                ip,
+                fsr
+            ),
+            Fault::Timeout { data } => write!(f, "timeout fault - data 0x{:X}", data),
+            Fault::Unknown { label } => write!(f, "unknown fault label {}", label),
+        }
+    }
+}
+
+/// What the init system does with a thread after it faulted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultPolicy {
+    /// Tear the thread down and reclaim its resources,
+    /// joining it returns Error::ThreadKilled
+    Kill,
+    /// Start the thread over from its entry point on its stack, the n-th
+    /// restart is delayed by n * backoff_yields yields of the root task while
+    /// it has no messages to handle. The thread is killed once it faults
+    /// after max_restarts restarts
+    Restart {
+        max_restarts: usize,
+        backoff_yields: usize,
+    },
+    /// Suspend the thread and keep it around for inspection
+    Suspend,
+    /// Suspend all threads and stop the system
+    Halt,
+}
+
+fn get_mr(idx: usize) -> seL4_Word {
+    unsafe { seL4_GetMR(idx as _) }
+}
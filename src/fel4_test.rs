@@ -2,10 +2,17 @@ use proptest::prelude::*;
 use proptest::test_runner::{TestCaseError, TestError, TestRunner};
 
 use alloc::string::String;
+use alloc::vec::Vec;
+use arch::{Arch, CurrentArch};
+use badge::{self, BadgeAllocator, BadgeKind};
+use core::cmp;
 use core::fmt;
+use error::Error;
 #[cfg(feature = "KernelPrinting")]
 use sel4_sys::DebugOutHandle;
 use sel4_sys::*;
+use slot_allocator::SlotAllocator;
+use vspace::{Region, VSpaceManager};
 
 #[cfg(feature = "KernelPrinting")]
 macro_rules! debug_print {
@@ -24,17 +31,57 @@ macro_rules! debug_println {
 #[cfg(feature = "KernelPrinting")]
 pub fn run() {
     debug_println!("\n\nrunning example tests");
-    let mut runner = TestRunner::default();
     let mut num_passed = 0;
     let mut num_failed = 0;
+    // a runner counts successes across runs, so each test gets a fresh one
     for found_success in [
         print_test_result(
             "test_message_info_predictability",
-            test_message_info_predictability(&mut runner),
+            test_message_info_predictability(&mut TestRunner::default()),
         ),
         print_test_result(
             "test_cap_rights_predictability",
-            test_cap_rights_predictability(&mut runner),
+            test_cap_rights_predictability(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_slot_alloc_free_round_trip",
+            test_slot_alloc_free_round_trip(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_slot_exhaustion",
+            test_slot_exhaustion(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_slot_alloc_range_contiguity",
+            test_slot_alloc_range_contiguity(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_slot_tail_bits_unused",
+            test_slot_tail_bits_unused(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_badge_namespaces_disjoint",
+            test_badge_namespaces_disjoint(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_badge_classify_round_trip",
+            test_badge_classify_round_trip(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_badge_double_free",
+            test_badge_double_free(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_vspace_no_overlaps",
+            test_vspace_no_overlaps(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_vspace_mapping_outside_reserved",
+            test_vspace_mapping_outside_reserved(&mut TestRunner::default()),
+        ),
+        print_test_result(
+            "test_vspace_free_reuse",
+            test_vspace_free_reuse(&mut TestRunner::default()),
         ),
     ].iter()
     {
@@ -117,6 +164,506 @@ fn test_cap_rights_predictability(
     })
 }
 
+fn slot_region(start: seL4_Word, num_slots: usize) -> seL4_SlotRegion {
+    seL4_SlotRegion {
+        start,
+        end: start + num_slots as seL4_Word,
+    }
+}
+
+fn test_slot_alloc_free_round_trip(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(u32, usize, usize)>> {
+    runner.run(&(0u32..0x1000, 1usize..300, 0usize..300), |&input| {
+        let (start, num_slots, count) = input;
+        let count = count % (num_slots + 1);
+        let start = start as seL4_Word;
+        let mut slots = SlotAllocator::new(slot_region(start, num_slots));
+
+        let mut allocated = Vec::new();
+        for _ in 0..count {
+            let slot = slots
+                .alloc()
+                .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+            if slot < start || slot >= start + num_slots as seL4_Word {
+                return Err(TestCaseError::fail(format!("slot {} out of range", slot)));
+            }
+            if allocated.contains(&slot) {
+                return Err(TestCaseError::fail(format!(
+                    "slot {} handed out twice",
+                    slot
+                )));
+            }
+            allocated.push(slot);
+        }
+
+        if slots.num_free() != num_slots - count {
+            return Err(TestCaseError::fail(format!(
+                "{} free after {} of {} allocated",
+                slots.num_free(),
+                count,
+                num_slots
+            )));
+        }
+
+        for &slot in allocated.iter() {
+            slots.free(slot);
+        }
+
+        if slots.num_free() != num_slots {
+            return Err(TestCaseError::fail(format!(
+                "{} of {} free after freeing all",
+                slots.num_free(),
+                num_slots
+            )));
+        }
+
+        // freed slots are handed out again
+        for _ in 0..num_slots {
+            slots
+                .alloc()
+                .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+        }
+
+        Ok(())
+    })
+}
+
+fn test_slot_exhaustion(runner: &mut TestRunner) -> Result<(), TestError<(usize, usize)>> {
+    runner.run(&(1usize..300, 1usize..8), |&input| {
+        let (num_slots, range) = input;
+        let mut slots = SlotAllocator::new(slot_region(0x100, num_slots));
+
+        for _ in 0..num_slots {
+            slots
+                .alloc()
+                .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+        }
+
+        if slots.alloc() != Err(Error::SlotsExhausted { count: 1 }) {
+            return Err(TestCaseError::fail("alloc succeeded on a full allocator"));
+        }
+        if slots.alloc_range(range) != Err(Error::SlotsExhausted { count: range }) {
+            return Err(TestCaseError::fail(
+                "alloc_range succeeded on a full allocator",
+            ));
+        }
+
+        Ok(())
+    })
+}
+
+fn test_slot_alloc_range_contiguity(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(usize, usize, usize, usize)>> {
+    runner.run(
+        &(1usize..300, 0usize..300, 1usize..5, 1usize..80),
+        |&input| {
+            let (num_slots, singles, stride, count) = input;
+            let singles = singles % (num_slots + 1);
+            let start: seL4_Word = 0x100;
+            let mut slots = SlotAllocator::new(slot_region(start, num_slots));
+
+            // leave holes between the allocated slots so the free slots are fragmented
+            let mut used = Vec::new();
+            used.resize(num_slots, false);
+            let mut allocated = Vec::new();
+            for _ in 0..singles {
+                allocated.push(
+                    slots
+                        .alloc()
+                        .map_err(|e| TestCaseError::fail(format!("{}", e)))?,
+                );
+            }
+            for (i, &slot) in allocated.iter().enumerate() {
+                if i % stride == 0 {
+                    used[(slot - start) as usize] = true;
+                } else {
+                    slots.free(slot);
+                }
+            }
+
+            let mut longest_run = 0;
+            let mut run_len = 0;
+            for &slot_used in used.iter() {
+                run_len = if slot_used { 0 } else { run_len + 1 };
+                longest_run = cmp::max(longest_run, run_len);
+            }
+
+            let first = match slots.alloc_range(count) {
+                Ok(first) => first,
+                Err(_) if longest_run < count => return Ok(()),
+                Err(e) => return Err(TestCaseError::fail(format!("{}", e))),
+            };
+
+            if first < start || first + count as seL4_Word > start + num_slots as seL4_Word {
+                return Err(TestCaseError::fail(format!(
+                    "range {}+{} out of range",
+                    first, count
+                )));
+            }
+
+            for slot in first..(first + count as seL4_Word) {
+                if used[(slot - start) as usize] {
+                    return Err(TestCaseError::fail(format!(
+                        "slot {} handed out twice",
+                        slot
+                    )));
+                }
+            }
+
+            // the range is in use now
+            while let Ok(slot) = slots.alloc() {
+                if slot >= first && slot < first + count as seL4_Word {
+                    return Err(TestCaseError::fail(format!(
+                        "slot {} handed out twice",
+                        slot
+                    )));
+                }
+            }
+
+            Ok(())
+        },
+    )
+}
+
+fn test_slot_tail_bits_unused(runner: &mut TestRunner) -> Result<(), TestError<(u32, usize)>> {
+    runner.run(&(0u32..0x1000, 1usize..300), |&input| {
+        let (start, num_slots) = input;
+        let start = start as seL4_Word;
+        let end = start + num_slots as seL4_Word;
+        let mut slots = SlotAllocator::new(slot_region(start, num_slots));
+
+        let mut count = 0;
+        while let Ok(slot) = slots.alloc() {
+            if slot >= end {
+                return Err(TestCaseError::fail(format!(
+                    "slot {} past the region's end {}",
+                    slot, end
+                )));
+            }
+            count += 1;
+        }
+
+        if count != num_slots {
+            return Err(TestCaseError::fail(format!(
+                "{} of {} slots handed out",
+                count, num_slots
+            )));
+        }
+
+        Ok(())
+    })
+}
+
+fn badge_kind(idx: u8) -> BadgeKind {
+    match idx % 3 {
+        0 => BadgeKind::Fault,
+        1 => BadgeKind::Ipc,
+        _ => BadgeKind::Notification,
+    }
+}
+
+fn test_badge_namespaces_disjoint(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(usize, usize, usize)>> {
+    runner.run(&(0usize..100, 0usize..100, 0usize..20), |&input| {
+        let (faults, ipcs, notifications) = input;
+        let mut badges = BadgeAllocator::new();
+
+        // interleave the namespaces so each allocation sees the others' state
+        let mut allocated = Vec::new();
+        for i in 0..cmp::max(faults, cmp::max(ipcs, notifications)) {
+            for &(kind, count) in [
+                (BadgeKind::Fault, faults),
+                (BadgeKind::Ipc, ipcs),
+                (BadgeKind::Notification, notifications),
+            ].iter()
+            {
+                if i < count {
+                    let badge = badges
+                        .alloc(kind)
+                        .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+                    allocated.push((kind, badge));
+                }
+            }
+        }
+
+        for (i, &(kind, badge)) in allocated.iter().enumerate() {
+            if badge == 0 {
+                return Err(TestCaseError::fail(format!("{:?} badge is unbadged", kind)));
+            }
+
+            for &(other_kind, other) in allocated[(i + 1)..].iter() {
+                if badge == other {
+                    return Err(TestCaseError::fail(format!(
+                        "badge 0x{:X} issued twice",
+                        badge
+                    )));
+                }
+
+                // pending notification badges are ORed together by the kernel
+                if kind == BadgeKind::Notification
+                    && other_kind == BadgeKind::Notification
+                    && badge::value(badge) & badge::value(other) != 0
+                {
+                    return Err(TestCaseError::fail(format!(
+                        "notification badges 0x{:X} and 0x{:X} share bits",
+                        badge, other
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn test_badge_classify_round_trip(runner: &mut TestRunner) -> Result<(), TestError<(u8, usize)>> {
+    runner.run(&(0u8..3, 1usize..100), |&input| {
+        let (kind, count) = input;
+        let kind = badge_kind(kind);
+        let count = if kind == BadgeKind::Notification {
+            cmp::min(count, 20)
+        } else {
+            count
+        };
+        let mut badges = BadgeAllocator::new();
+
+        if badge::classify(0).is_some() {
+            return Err(TestCaseError::fail("unbadged 0 classified"));
+        }
+
+        for _ in 0..count {
+            let badge = badges
+                .alloc(kind)
+                .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+
+            if badge::classify(badge) != Some(kind) {
+                return Err(TestCaseError::fail(format!(
+                    "{:?} badge 0x{:X} classified as {:?}",
+                    kind,
+                    badge,
+                    badge::classify(badge)
+                )));
+            }
+
+            badge::validate(badge).map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+        }
+
+        Ok(())
+    })
+}
+
+fn test_badge_double_free(runner: &mut TestRunner) -> Result<(), TestError<(u8, usize, usize)>> {
+    runner.run(&(0u8..3, 1usize..20, 0usize..20), |&input| {
+        let (kind, count, idx) = input;
+        let kind = badge_kind(kind);
+        let mut badges = BadgeAllocator::new();
+
+        let mut allocated = Vec::new();
+        for _ in 0..count {
+            allocated.push(
+                badges
+                    .alloc(kind)
+                    .map_err(|e| TestCaseError::fail(format!("{}", e)))?,
+            );
+        }
+
+        let badge = allocated[idx % count];
+        badges
+            .free(badge)
+            .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+
+        if badges.free(badge) != Err(Error::BadgeNotAllocated { badge }) {
+            return Err(TestCaseError::fail(format!(
+                "badge 0x{:X} freed twice",
+                badge
+            )));
+        }
+
+        // the freed badge is handed out again, the others stay in use
+        let reused = badges
+            .alloc(kind)
+            .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+        if reused != badge {
+            return Err(TestCaseError::fail(format!(
+                "expected badge 0x{:X} back, got 0x{:X}",
+                badge, reused
+            )));
+        }
+
+        Ok(())
+    })
+}
+
+fn test_vspace_no_overlaps(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(Vec<(usize, usize)>, Vec<(usize, usize)>)>> {
+    runner.run(
+        &(
+            prop::collection::vec((0usize..0x100, 1usize..0x10), 0..10),
+            prop::collection::vec((1usize..0x40000, 0usize..20), 1..30),
+        ),
+        |input| {
+            let (ref reservations, ref allocations) = input;
+            let page_size = 1 << CurrentArch::PAGE_BITS;
+            let mut vspace = VSpaceManager::new();
+            let mut regions: Vec<Region> = Vec::new();
+
+            // fixed reservations in the way of the allocations
+            for &(page, num_pages) in reservations.iter() {
+                let vaddr = CurrentArch::VSPACE_START + (page * page_size) as seL4_Word;
+                let region = Region::new(vaddr, num_pages * page_size);
+                let overlaps = regions.iter().any(|r| r.overlaps(&region));
+
+                match vspace.reserve(vaddr, region.size()) {
+                    Ok(()) if !overlaps => regions.push(region),
+                    Err(Error::VaddrInUse { .. }) if overlaps => (),
+                    result => {
+                        return Err(TestCaseError::fail(format!(
+                            "reserving {:?} returned {:?}",
+                            region, result
+                        )))
+                    }
+                }
+            }
+
+            for &(size, align_bits) in allocations.iter() {
+                let vaddr = vspace
+                    .alloc(size, align_bits)
+                    .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+                let region = Region::new(vaddr, size);
+
+                if vaddr & ((1 << align_bits) - 1) != 0 {
+                    return Err(TestCaseError::fail(format!(
+                        "0x{:X} is not aligned to {} bits",
+                        vaddr, align_bits
+                    )));
+                }
+
+                if vaddr < CurrentArch::VSPACE_START || region.end > CurrentArch::VSPACE_END {
+                    return Err(TestCaseError::fail(format!(
+                        "{:?} is outside the VSpace",
+                        region
+                    )));
+                }
+
+                if let Some(other) = regions.iter().find(|r| r.overlaps(&region)) {
+                    return Err(TestCaseError::fail(format!(
+                        "{:?} overlaps {:?}",
+                        region, other
+                    )));
+                }
+
+                regions.push(region);
+            }
+
+            Ok(())
+        },
+    )
+}
+
+fn test_vspace_mapping_outside_reserved(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(usize, usize, usize)>> {
+    runner.run(&(1usize..0x20, 0usize..0x60, 0usize..3), |&input| {
+        let (num_pages, page, size_bits) = input;
+        let page_size = 1 << CurrentArch::PAGE_BITS;
+        let size_bits = CurrentArch::PAGE_BITS + size_bits;
+        let mut vspace = VSpaceManager::new();
+
+        let reserved = Region::new(
+            CurrentArch::VSPACE_START + (0x20 * page_size) as seL4_Word,
+            num_pages * page_size,
+        );
+        vspace
+            .reserve(reserved.start, reserved.size())
+            .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+
+        // frames in front of, across, inside and past the reserved region
+        let vaddr = CurrentArch::VSPACE_START + (page * page_size) as seL4_Word;
+        let frame = Region::new(vaddr, 1 << size_bits);
+        let inside = frame.start >= reserved.start && frame.end <= reserved.end;
+
+        let expected = if inside {
+            Ok(())
+        } else {
+            Err(Error::VaddrNotReserved {
+                vaddr,
+                size: frame.size(),
+            })
+        };
+        let result = vspace.add_mapping(vaddr, size_bits, 0);
+        if result != expected {
+            return Err(TestCaseError::fail(format!(
+                "mapping {:?} with {:?} reserved returned {:?}",
+                frame, reserved, result
+            )));
+        }
+
+        if vspace.is_mapped(vaddr) != inside {
+            return Err(TestCaseError::fail(format!(
+                "mapping state of 0x{:X} is wrong",
+                vaddr
+            )));
+        }
+
+        Ok(())
+    })
+}
+
+fn test_vspace_free_reuse(
+    runner: &mut TestRunner,
+) -> Result<(), TestError<(Vec<(usize, usize)>, usize)>> {
+    runner.run(
+        &(
+            prop::collection::vec((1usize..0x40000, 0usize..20), 1..30),
+            0usize..30,
+        ),
+        |input| {
+            let (ref allocations, idx) = input;
+            let mut vspace = VSpaceManager::new();
+
+            let mut allocated = Vec::new();
+            for &(size, align_bits) in allocations.iter() {
+                allocated.push(
+                    vspace
+                        .alloc(size, align_bits)
+                        .map_err(|e| TestCaseError::fail(format!("{}", e)))?,
+                );
+            }
+
+            let idx = idx % allocations.len();
+            let (size, align_bits) = allocations[idx];
+            let vaddr = allocated[idx];
+
+            if vspace.free(vaddr) != Some(Region::new(vaddr, size)) {
+                return Err(TestCaseError::fail(format!("0x{:X} was not freed", vaddr)));
+            }
+            if vspace.find_region(vaddr).is_some() || vspace.free(vaddr).is_some() {
+                return Err(TestCaseError::fail(format!(
+                    "0x{:X} is still reserved",
+                    vaddr
+                )));
+            }
+
+            // first fit, the range in front of the freed one is still too small
+            let reused = vspace
+                .alloc(size, align_bits)
+                .map_err(|e| TestCaseError::fail(format!("{}", e)))?;
+            if reused != vaddr {
+                return Err(TestCaseError::fail(format!(
+                    "expected 0x{:X} back, got 0x{:X}",
+                    vaddr, reused
+                )));
+            }
+
+            Ok(())
+        },
+    )
+}
+
 /// Prints a summary of the test output.
 /// Returns true if the test succeeded, false otherwise.
 fn print_test_result<T: fmt::Debug>(
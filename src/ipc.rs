@@ -0,0 +1,809 @@
+//! Typed messages over endpoints. A message is sent with its label and
+//! a fixed number of message registers, read from and written to the
+//! calling thread's IPC buffer, nothing is allocated.
+//!
+//! ```ignore
+//! let pong: Pong = ipc::call(ep_cap, &Ping { seq: 1 })?;
+//! ```
+//!
+//! Byte payloads of any size are sent with the `*_bytes` functions, in
+//! the message registers if they fit, otherwise through a SharedBuffer.
+//!
+//! Caps are sent with the `*_with_caps` functions. The receiver picks the
+//! empty slot a cap is moved to with set_receive_slot beforehand, a cap
+//! badged for the very endpoint it is sent on arrives unwrapped as its badge.
+//!
+//! A server that answers calls out of order saves their reply caps in
+//! a ReplyCapPool and replies later through the PendingReply.
+//!
+//! A Server runs the receive and reply loop of a service thread,
+//! dispatching requests to handlers by their label:
+//!
+//! ```ignore
+//! fn ping(count: &mut usize, client_badge: seL4_Word, ping: Ping) -> Pong {
+//!     *count += 1;
+//!     Pong { seq: ping.seq }
+//! }
+//!
+//! Server::new(ep_cap, 0).handle(ping).run();
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{cmp, mem, ptr};
+use error::{Error, Operation};
+use sel4_sys::*;
+
+/// Header bit of a byte payload placed in a SharedBuffer
+const SHARED_FLAG: seL4_Word = 1;
+
+/// Bytes of payload that fit in the message registers after the header word
+pub const INLINE_CAPACITY: usize = (seL4_MsgMaxLength as usize - 1) * mem::size_of::<seL4_Word>();
+
+/// Memory mapped in the address space of both ends of an endpoint for
+/// payloads larger than INLINE_CAPACITY. Each transfer overwrites the
+/// previous one, the receiver copies the payload out before replying.
+/// It is not Copy, freeing the buffer consumes the handle so it cannot
+/// be used after the memory is unmapped.
+#[derive(Debug)]
+pub struct SharedBuffer {
+    vaddr: seL4_Word,
+    size: usize,
+}
+
+impl SharedBuffer {
+    /// The memory at [vaddr, vaddr + size) must stay mapped read-write
+    /// while the buffer is in use
+    pub unsafe fn from_raw(vaddr: seL4_Word, size: usize) -> SharedBuffer {
+        SharedBuffer { vaddr, size }
+    }
+
+    pub fn vaddr(&self) -> seL4_Word {
+        self.vaddr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A request or response that serializes into message registers
+pub trait Message: Sized {
+    /// Label the message is sent with, must fit in 20 bits to be
+    /// portable to 32-bit kernels and is unique among the messages
+    /// received on an endpoint
+    const LABEL: seL4_Word;
+
+    /// Number of message registers, at most seL4_MsgMaxLength
+    const LENGTH: usize;
+
+    /// Writes the message into words, which has LENGTH entries
+    fn encode(&self, words: &mut [seL4_Word]);
+
+    /// Reads the message from words, which has LENGTH entries
+    fn decode(words: &[seL4_Word]) -> Self;
+}
+
+/// A message just received, its registers are only valid until
+/// the thread's next IPC
+#[derive(Debug, Copy, Clone)]
+pub struct Received {
+    badge: seL4_Word,
+    label: seL4_Word,
+    length: usize,
+    extra_caps: usize,
+    caps_unwrapped: seL4_Word,
+}
+
+impl Received {
+    fn new(badge: seL4_Word, msg_info: seL4_MessageInfo_t) -> Received {
+        Received {
+            badge,
+            label: unsafe { seL4_MessageInfo_get_label(msg_info) } as seL4_Word,
+            length: unsafe { seL4_MessageInfo_get_length(msg_info) } as usize,
+            extra_caps: unsafe { seL4_MessageInfo_get_extraCaps(msg_info) } as usize,
+            caps_unwrapped: unsafe { seL4_MessageInfo_get_capsUnwrapped(msg_info) } as seL4_Word,
+        }
+    }
+
+    /// Badge of the cap the message was sent on, 0 for replies
+    pub fn badge(&self) -> seL4_Word {
+        self.badge
+    }
+
+    pub fn label(&self) -> seL4_Word {
+        self.label
+    }
+
+    /// Number of message registers received
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Number of caps received, transferred to the receive slot or unwrapped
+    pub fn extra_caps(&self) -> usize {
+        self.extra_caps
+    }
+
+    /// True if the i-th cap was transferred to the receive slot
+    pub fn cap_transferred(&self, i: usize) -> bool {
+        i < self.extra_caps && self.caps_unwrapped & (1 << i) == 0
+    }
+
+    /// Badge of the i-th cap if it was badged for the receiving endpoint
+    /// and so unwrapped instead of transferred
+    pub fn unwrapped_badge(&self, i: usize) -> Option<seL4_Word> {
+        if i < self.extra_caps && self.caps_unwrapped & (1 << i) != 0 {
+            Some(unsafe { seL4_GetBadge(i as _) })
+        } else {
+            None
+        }
+    }
+
+    /// True if the message has the label of M
+    pub fn is<M: Message>(&self) -> bool {
+        self.label == M::LABEL
+    }
+
+    /// Decodes the message as M, fails if the label or length do not match
+    pub fn decode<M: Message>(&self) -> Result<M, Error> {
+        self.expect(M::LABEL, M::LENGTH)?;
+
+        let mut words = [0; seL4_MsgMaxLength as usize];
+        let length = self.read_words(&mut words);
+        Ok(M::decode(&words[..length]))
+    }
+
+    /// Checks the message has the label and length, an ErrorReply
+    /// of a Server is returned as Error::Rejected
+    pub fn expect(&self, label: seL4_Word, length: usize) -> Result<(), Error> {
+        if self.label == ErrorReply::LABEL && label != ErrorReply::LABEL {
+            let reply: ErrorReply = self.decode()?;
+            return Err(Error::Rejected {
+                error: reply.error,
+                label: reply.label,
+            });
+        }
+
+        if self.label != label {
+            return Err(Error::UnexpectedLabel {
+                expected: label,
+                label: self.label,
+            });
+        }
+
+        if self.length != length {
+            return Err(Error::MessageLength {
+                label: self.label,
+                expected: length,
+                length: self.length,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The i-th message register received, None past the message's length
+    pub fn word(&self, i: usize) -> Option<seL4_Word> {
+        if i < self.length {
+            Some(unsafe { seL4_GetMR(i as _) })
+        } else {
+            None
+        }
+    }
+
+    /// Copies the message registers received into buf, returns their number
+    pub fn read_words(&self, buf: &mut [seL4_Word]) -> usize {
+        let length = cmp::min(self.length, buf.len());
+        for (i, word) in buf[..length].iter_mut().enumerate() {
+            *word = unsafe { seL4_GetMR(i as _) };
+        }
+        length
+    }
+
+    /// Copies the byte payload of the message into buf, from the shared
+    /// buffer if the sender used it, returns the payload's size
+    pub fn read_bytes(
+        &self,
+        shared: Option<&SharedBuffer>,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        if self.length == 0 {
+            return Err(Error::MessageLength {
+                label: self.label,
+                expected: 1,
+                length: 0,
+            });
+        }
+
+        let header = unsafe { seL4_GetMR(0) };
+        let size = (header >> 1) as usize;
+
+        let src = if header & SHARED_FLAG == 0 {
+            let expected =
+                1 + (size + mem::size_of::<seL4_Word>() - 1) / mem::size_of::<seL4_Word>();
+            if self.length != expected {
+                return Err(Error::MessageLength {
+                    label: self.label,
+                    expected,
+                    length: self.length,
+                });
+            }
+            unsafe { msg_ptr().add(1) as *const u8 }
+        } else {
+            match shared {
+                Some(shared) if size <= shared.size => shared.vaddr as *const u8,
+                _ => {
+                    return Err(Error::PayloadTooLarge {
+                        size,
+                        capacity: shared.map_or(0, |s| s.size),
+                    })
+                }
+            }
+        };
+
+        if size > buf.len() {
+            return Err(Error::PayloadTooLarge {
+                size,
+                capacity: buf.len(),
+            });
+        }
+
+        unsafe { ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), size) };
+
+        Ok(size)
+    }
+}
+
+/// Sends req and waits for the response, fails if the response is not a Resp
+pub fn call<Req: Message, Resp: Message>(ep_cap: seL4_CPtr, req: &Req) -> Result<Resp, Error> {
+    let msg_info = unsafe { seL4_Call(ep_cap, load(req, 0)) };
+    Received::new(0, msg_info).decode()
+}
+
+/// Sends the words with the label and waits for the response
+pub fn call_words(ep_cap: seL4_CPtr, label: seL4_Word, words: &[seL4_Word]) -> Received {
+    let msg_info = unsafe { seL4_Call(ep_cap, load_words(label, words)) };
+    Received::new(0, msg_info)
+}
+
+/// Sends msg, blocking until it is received
+pub fn send<M: Message>(ep_cap: seL4_CPtr, msg: &M) {
+    unsafe { seL4_Send(ep_cap, load(msg, 0)) };
+}
+
+/// Waits for a message on the endpoint
+pub fn recv(ep_cap: seL4_CPtr) -> Received {
+    let mut badge: seL4_Word = 0;
+    let msg_info = unsafe { seL4_Recv(ep_cap, &mut badge) };
+    Received::new(badge, msg_info)
+}
+
+/// Replies to the last call received
+pub fn reply<M: Message>(msg: &M) {
+    unsafe { seL4_Reply(load(msg, 0)) };
+}
+
+/// Replies to the last call received and waits for the next message
+pub fn reply_recv<M: Message>(ep_cap: seL4_CPtr, msg: &M) -> Received {
+    let mut badge: seL4_Word = 0;
+    let msg_info = unsafe { seL4_ReplyRecv(ep_cap, load(msg, 0), &mut badge) };
+    Received::new(badge, msg_info)
+}
+
+/// Sends a byte payload with the label, see SharedBuffer
+pub fn send_bytes(
+    ep_cap: seL4_CPtr,
+    label: seL4_Word,
+    bytes: &[u8],
+    shared: Option<&SharedBuffer>,
+) -> Result<(), Error> {
+    let msg_info = load_bytes(label, bytes, shared)?;
+    unsafe { seL4_Send(ep_cap, msg_info) };
+    Ok(())
+}
+
+/// Sends a byte payload with the label and waits for the response,
+/// the response's payload is read with Received::read_bytes
+pub fn call_bytes(
+    ep_cap: seL4_CPtr,
+    label: seL4_Word,
+    bytes: &[u8],
+    shared: Option<&SharedBuffer>,
+) -> Result<Received, Error> {
+    let msg_info = load_bytes(label, bytes, shared)?;
+    let msg_info = unsafe { seL4_Call(ep_cap, msg_info) };
+    Ok(Received::new(0, msg_info))
+}
+
+/// Replies to the last call received with a byte payload
+pub fn reply_bytes(
+    label: seL4_Word,
+    bytes: &[u8],
+    shared: Option<&SharedBuffer>,
+) -> Result<(), Error> {
+    let msg_info = load_bytes(label, bytes, shared)?;
+    unsafe { seL4_Reply(msg_info) };
+    Ok(())
+}
+
+/// Writes the payload's header and, if they fit, its bytes into the
+/// message registers, larger payloads go to the shared buffer
+fn load_bytes(
+    label: seL4_Word,
+    bytes: &[u8],
+    shared: Option<&SharedBuffer>,
+) -> Result<seL4_MessageInfo_t, Error> {
+    let size = bytes.len();
+
+    let length = if size <= INLINE_CAPACITY {
+        unsafe {
+            seL4_SetMR(0, (size as seL4_Word) << 1);
+            ptr::copy_nonoverlapping(bytes.as_ptr(), msg_ptr().add(1) as *mut u8, size)
+        };
+        1 + (size + mem::size_of::<seL4_Word>() - 1) / mem::size_of::<seL4_Word>()
+    } else {
+        match shared {
+            Some(shared) if size <= shared.size => {
+                unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), shared.vaddr as *mut u8, size) };
+                unsafe { seL4_SetMR(0, (size as seL4_Word) << 1 | SHARED_FLAG) };
+                1
+            }
+            _ => {
+                let capacity =
+                    shared.map_or(INLINE_CAPACITY, |s| cmp::max(s.size, INLINE_CAPACITY));
+                return Err(Error::PayloadTooLarge { size, capacity });
+            }
+        }
+    };
+
+    Ok(unsafe { seL4_MessageInfo_new(label as _, 0, 0, length as _) })
+}
+
+/// Sends msg with caps, at most seL4_MsgMaxExtraCaps
+pub fn send_with_caps<M: Message>(
+    ep_cap: seL4_CPtr,
+    msg: &M,
+    caps: &[seL4_CPtr],
+) -> Result<(), Error> {
+    let msg_info = load_with_caps(msg, caps)?;
+    unsafe { seL4_Send(ep_cap, msg_info) };
+    Ok(())
+}
+
+/// Sends req with caps and waits for the response, caps in the response
+/// are moved to the receive slot
+pub fn call_with_caps<Req: Message>(
+    ep_cap: seL4_CPtr,
+    req: &Req,
+    caps: &[seL4_CPtr],
+) -> Result<Received, Error> {
+    let msg_info = load_with_caps(req, caps)?;
+    let msg_info = unsafe { seL4_Call(ep_cap, msg_info) };
+    Ok(Received::new(0, msg_info))
+}
+
+/// Replies to the last call received with caps
+pub fn reply_with_caps<M: Message>(msg: &M, caps: &[seL4_CPtr]) -> Result<(), Error> {
+    let msg_info = load_with_caps(msg, caps)?;
+    unsafe { seL4_Reply(msg_info) };
+    Ok(())
+}
+
+/// Moves the first cap of the next message received into the empty slot
+/// of the root task's CSpace, which all threads share
+pub fn set_receive_slot(slot_cap: seL4_CPtr) {
+    set_receive_path(seL4_CapInitThreadCNode as _, slot_cap, seL4_WordBits as _);
+}
+
+/// Moves the first cap of the next message received into the empty slot
+/// at index, looked up to depth bits in cnode_cap
+pub fn set_receive_path(cnode_cap: seL4_CPtr, index: seL4_CPtr, depth: seL4_Word) {
+    unsafe { seL4_SetCapReceivePath(cnode_cap, index, depth) };
+}
+
+fn load_with_caps<M: Message>(msg: &M, caps: &[seL4_CPtr]) -> Result<seL4_MessageInfo_t, Error> {
+    if caps.len() > seL4_MsgMaxExtraCaps as usize {
+        return Err(Error::TooManyCaps { count: caps.len() });
+    }
+
+    for (i, &cap) in caps.iter().enumerate() {
+        unsafe { seL4_SetCap(i as _, cap) };
+    }
+
+    Ok(load(msg, caps.len()))
+}
+
+/// Why a Server rejected a request
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    /// No handler is registered for the label
+    UnknownLabel,
+    /// The request has the wrong length for its label
+    BadRequest,
+}
+
+/// Reply of a Server to a request it could not handle, turned into
+/// Error::Rejected by Received::decode on the client side
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ErrorReply {
+    pub error: ServerError,
+    /// label of the rejected request
+    pub label: seL4_Word,
+}
+
+impl Message for ErrorReply {
+    /// Reserved, one below thread::EXIT_LABEL
+    const LABEL: seL4_Word = 0xF_FFFE;
+    const LENGTH: usize = 2;
+
+    fn encode(&self, words: &mut [seL4_Word]) {
+        words[0] = match self.error {
+            ServerError::UnknownLabel => 1,
+            ServerError::BadRequest => 2,
+        };
+        words[1] = self.label;
+    }
+
+    fn decode(words: &[seL4_Word]) -> ErrorReply {
+        ErrorReply {
+            error: match words[0] {
+                1 => ServerError::UnknownLabel,
+                _ => ServerError::BadRequest,
+            },
+            label: words[1],
+        }
+    }
+}
+
+/// A value passed in a single message register
+pub trait MessageWord: Sized {
+    fn into_word(self) -> seL4_Word;
+    fn from_word(word: seL4_Word) -> Self;
+}
+
+macro_rules! impl_message_word {
+    ($($ty:ty),*) => {
+        $(
+            impl MessageWord for $ty {
+                fn into_word(self) -> seL4_Word {
+                    self as seL4_Word
+                }
+
+                fn from_word(word: seL4_Word) -> $ty {
+                    word as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_message_word!(u8, u16, u32, usize, i8, i16, i32, isize);
+
+// a 64-bit value only fits a single message register on 64-bit targets
+#[cfg(target_pointer_width = "64")]
+impl_message_word!(u64, i64);
+
+impl MessageWord for bool {
+    fn into_word(self) -> seL4_Word {
+        self as seL4_Word
+    }
+
+    fn from_word(word: seL4_Word) -> bool {
+        word != 0
+    }
+}
+
+impl MessageWord for () {
+    fn into_word(self) -> seL4_Word {
+        0
+    }
+
+    fn from_word(_: seL4_Word) {}
+}
+
+/// Handler reading the request and writing the reply itself,
+/// returns the message info of the reply
+type Handler<S> = Box<dyn FnMut(&mut S, &Received) -> Result<seL4_MessageInfo_t, Error>>;
+
+/// Receive and reply loop of a service thread, requests are passed to the
+/// handler registered for their label along with the badge of the client.
+/// The handlers are boxed when registered, servers are built before serving
+pub struct Server<S> {
+    ep_cap: seL4_CPtr,
+    state: S,
+    handlers: Vec<(seL4_Word, Handler<S>)>,
+}
+
+impl<S> Server<S> {
+    /// Server for requests on ep_cap, handlers get a mutable reference to state
+    pub fn new(ep_cap: seL4_CPtr, state: S) -> Server<S> {
+        Server {
+            ep_cap,
+            state,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Registers the handler for requests with the label of Req,
+    /// it is called with the badge of the client's cap
+    pub fn handle<Req, Resp>(self, handler: fn(&mut S, seL4_Word, Req) -> Resp) -> Server<S>
+    where
+        S: 'static,
+        Req: Message + 'static,
+        Resp: Message + 'static,
+    {
+        self.handle_raw(Req::LABEL, move |state: &mut S, received: &Received| {
+            let req = received.decode::<Req>()?;
+            let resp = handler(state, received.badge(), req);
+            Ok(load(&resp, 0))
+        })
+    }
+
+    /// Registers a handler for requests with the label that
+    /// decodes the request and encodes its reply itself
+    pub fn handle_raw<F>(mut self, label: seL4_Word, handler: F) -> Server<S>
+    where
+        S: 'static,
+        F: FnMut(&mut S, &Received) -> Result<seL4_MessageInfo_t, Error> + 'static,
+    {
+        self.add_handler(label, Box::new(handler));
+        self
+    }
+
+    pub fn ep_cap(&self) -> seL4_CPtr {
+        self.ep_cap
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Serves requests forever
+    pub fn run(&mut self) -> ! {
+        let mut received = recv(self.ep_cap);
+
+        loop {
+            let msg_info = self.dispatch(&received);
+
+            let mut badge: seL4_Word = 0;
+            let msg_info = unsafe { seL4_ReplyRecv(self.ep_cap, msg_info, &mut badge) };
+            received = Received::new(badge, msg_info);
+        }
+    }
+
+    /// Serves the given number of requests, the last one is
+    /// replied to without waiting for another
+    pub fn serve(&mut self, requests: usize) {
+        if requests == 0 {
+            return;
+        }
+
+        let mut received = recv(self.ep_cap);
+
+        for _ in 1..requests {
+            let msg_info = self.dispatch(&received);
+
+            let mut badge: seL4_Word = 0;
+            let msg_info = unsafe { seL4_ReplyRecv(self.ep_cap, msg_info, &mut badge) };
+            received = Received::new(badge, msg_info);
+        }
+
+        let msg_info = self.dispatch(&received);
+        unsafe { seL4_Reply(msg_info) };
+    }
+
+    fn add_handler(&mut self, label: seL4_Word, handler: Handler<S>) {
+        assert!(
+            label != ErrorReply::LABEL,
+            "Label is reserved for error replies"
+        );
+        assert!(
+            self.handlers.iter().all(|&(l, _)| l != label),
+            "Label already has a handler"
+        );
+
+        self.handlers.push((label, handler));
+    }
+
+    /// Runs the request's handler, returns the reply to send
+    fn dispatch(&mut self, received: &Received) -> seL4_MessageInfo_t {
+        let handler = self
+            .handlers
+            .iter_mut()
+            .find(|&&mut (label, _)| label == received.label());
+
+        let error = match handler {
+            Some(&mut (_, ref mut handler)) => match handler(&mut self.state, received) {
+                Ok(msg_info) => return msg_info,
+                Err(_) => ServerError::BadRequest,
+            },
+            None => ServerError::UnknownLabel,
+        };
+
+        load(
+            &ErrorReply {
+                error,
+                label: received.label(),
+            },
+            0,
+        )
+    }
+}
+
+/// Reply cap of a call saved in a ReplyCapPool, replying consumes it.
+/// Dropping it leaves the caller blocked and the pool's slot in use
+#[derive(Debug)]
+#[must_use]
+pub struct PendingReply {
+    slot_cap: seL4_CPtr,
+}
+
+/// Slots of the root task's CSpace a server saves reply caps to, so it can
+/// receive more calls before answering the earlier ones
+#[derive(Debug)]
+pub struct ReplyCapPool {
+    first_slot_cap: seL4_CPtr,
+    count: usize,
+    /// bit i is set if slot first_slot_cap + i holds a reply cap
+    used: u64,
+}
+
+impl ReplyCapPool {
+    /// Largest number of slots in a pool
+    pub const MAX_SLOTS: usize = 64;
+
+    /// The count empty slots from first_slot_cap on must be
+    /// reserved for the pool while it is in use, see check_count
+    pub unsafe fn from_raw(first_slot_cap: seL4_CPtr, count: usize) -> Result<ReplyCapPool, Error> {
+        ReplyCapPool::check_count(count)?;
+
+        Ok(ReplyCapPool {
+            first_slot_cap,
+            count,
+            used: 0,
+        })
+    }
+
+    /// Checks a pool can have count slots, at least one and at most MAX_SLOTS
+    pub fn check_count(count: usize) -> Result<(), Error> {
+        if count == 0 || count > ReplyCapPool::MAX_SLOTS {
+            return Err(Error::InvalidPoolSize {
+                count,
+                max: ReplyCapPool::MAX_SLOTS,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn first_slot_cap(&self) -> seL4_CPtr {
+        self.first_slot_cap
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Number of saved reply caps not answered yet
+    pub fn pending(&self) -> usize {
+        self.used.count_ones() as usize
+    }
+
+    /// Saves the reply cap of the call just received, must be called before
+    /// the thread's next receive, which would overwrite it
+    pub fn save(&mut self) -> Result<PendingReply, Error> {
+        let idx = match (0..self.count).find(|&i| self.used & (1 << i) == 0) {
+            Some(idx) => idx,
+            None => return Err(Error::SlotsExhausted { count: 1 }),
+        };
+        let slot_cap = self.first_slot_cap + idx as seL4_CPtr;
+
+        let err: seL4_Error = unsafe {
+            seL4_CNode_SaveCaller(seL4_CapInitThreadCNode as _, slot_cap, seL4_WordBits as _)
+        };
+        Error::check(Operation::CNodeSaveCaller, slot_cap, err)?;
+
+        self.used |= 1 << idx;
+
+        Ok(PendingReply { slot_cap })
+    }
+
+    /// Answers the saved call with msg and frees the slot
+    pub fn reply<M: Message>(&mut self, pending: PendingReply, msg: &M) -> Result<(), Error> {
+        self.check(&pending)?;
+        unsafe { seL4_Send(pending.slot_cap, load(msg, 0)) };
+        self.release(pending)
+    }
+
+    /// Answers the saved call with msg and caps and frees the slot
+    pub fn reply_with_caps<M: Message>(
+        &mut self,
+        pending: PendingReply,
+        msg: &M,
+        caps: &[seL4_CPtr],
+    ) -> Result<(), Error> {
+        self.check(&pending)?;
+        let msg_info = load_with_caps(msg, caps)?;
+        unsafe { seL4_Send(pending.slot_cap, msg_info) };
+        self.release(pending)
+    }
+
+    /// Frees the slot without answering, the caller stays blocked
+    pub fn discard(&mut self, pending: PendingReply) -> Result<(), Error> {
+        self.release(pending)
+    }
+
+    /// Deletes what is left of the reply cap, the kernel
+    /// already removes it once it is used
+    fn release(&mut self, pending: PendingReply) -> Result<(), Error> {
+        self.check(&pending)?;
+        let slot_cap = pending.slot_cap;
+
+        let err: seL4_Error = unsafe {
+            seL4_CNode_Delete(seL4_CapInitThreadCNode as _, slot_cap, seL4_WordBits as _)
+        };
+        Error::check(Operation::CNodeDelete, slot_cap, err)?;
+
+        self.used &= !(1 << (slot_cap - self.first_slot_cap));
+
+        Ok(())
+    }
+
+    /// Checks the reply cap was saved by this pool and not released yet
+    fn check(&self, pending: &PendingReply) -> Result<(), Error> {
+        let slot_cap = pending.slot_cap;
+        let in_pool = slot_cap >= self.first_slot_cap
+            && slot_cap < self.first_slot_cap + self.count as seL4_CPtr
+            && self.used & (1 << (slot_cap - self.first_slot_cap)) != 0;
+
+        if !in_pool {
+            return Err(Error::NotInPool { slot: slot_cap });
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the words into the message registers, returns the
+/// message info to send them with the label
+pub fn load_words(label: seL4_Word, words: &[seL4_Word]) -> seL4_MessageInfo_t {
+    assert!(
+        words.len() <= seL4_MsgMaxLength as usize,
+        "Message does not fit in the IPC buffer"
+    );
+
+    for (i, &word) in words.iter().enumerate() {
+        unsafe { seL4_SetMR(i as _, word) };
+    }
+
+    unsafe { seL4_MessageInfo_new(label as _, 0, 0, words.len() as _) }
+}
+
+/// Writes msg into the message registers, extra_caps caps are set already
+fn load<M: Message>(msg: &M, extra_caps: usize) -> seL4_MessageInfo_t {
+    assert!(
+        M::LENGTH <= seL4_MsgMaxLength as usize,
+        "Message does not fit in the IPC buffer"
+    );
+
+    let mut words = [0; seL4_MsgMaxLength as usize];
+    msg.encode(&mut words[..M::LENGTH]);
+
+    for (i, &word) in words[..M::LENGTH].iter().enumerate() {
+        unsafe { seL4_SetMR(i as _, word) };
+    }
+
+    unsafe { seL4_MessageInfo_new(M::LABEL as _, 0, extra_caps as _, M::LENGTH as _) }
+}
+
+/// First message register in the calling thread's IPC buffer, for copying
+/// byte payloads. No reference to the registers is kept, so the next IPC
+/// overwriting them cannot invalidate a borrow
+fn msg_ptr() -> *mut seL4_Word {
+    unsafe { (*seL4_GetIPCBuffer()).msg.as_mut_ptr() }
+}
@@ -1,38 +1,86 @@
 #![no_std]
 #![cfg_attr(feature = "alloc", feature(alloc))]
 
+#[cfg_attr(feature = "test", macro_use)]
 extern crate alloc;
+#[cfg(feature = "test")]
+extern crate proptest;
 extern crate sel4_sys;
 
 #[macro_use]
 mod macros;
+mod arch;
+mod badge;
 mod bootinfo_manager;
+mod error;
+mod fault;
+#[cfg(feature = "test")]
+pub mod fel4_test;
+pub mod ipc;
+mod registry;
+mod slot_allocator;
+mod thread;
 mod thread_a;
 mod thread_b;
+mod untyped_allocator;
+mod vspace;
 
 use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
+use arch::{Arch, CurrentArch};
+use badge::BadgeAllocator;
 use bootinfo_manager::BootInfoManager;
-use core::mem;
+use registry::ThreadRegistry;
 use sel4_sys::*;
 
+pub use badge::BadgeKind;
+pub use error::{Error, KernelError, Operation};
+pub use fault::{Fault, FaultPolicy};
+pub use ipc::Message;
+pub use registry::ThreadId;
+pub use thread::{IntoExitCode, ThreadBuilder, ThreadHandle, EXIT_LABEL};
+pub use vspace::{CachePolicy, MapFlags, Rights};
+
 #[cfg(feature = "KernelPrinting")]
 use sel4_sys::DebugOutHandle;
 
-/// size of thread stack in bytes
-const THREAD_STACK_SIZE: usize = 4096;
+/// Number of exit codes of threads not joined yet that are kept
+const MAX_EXIT_CODES: usize = 64;
 
-struct ThreadInfo {
-    tcb_cap: seL4_CPtr,
+pub struct InitSystem {
+    bi_mngr: BootInfoManager,
     fault_ep_cap: seL4_CPtr,
-    fault_ep_badge: seL4_Word,
-    ipc_ep_cap: seL4_CPtr,
-    ipc_ep_badge: seL4_Word,
+    badges: BadgeAllocator,
+    threads: ThreadRegistry,
+    /// exit codes, or the fault of killed threads, of threads that are
+    /// gone but were not joined yet, the oldest MAX_EXIT_CODES are kept
+    exit_codes: VecDeque<(ThreadId, Result<seL4_Word, Fault>)>,
+    fault_handler: Option<Box<dyn FnMut(ThreadHandle, &Fault)>>,
+    /// threads waiting to be restarted and the yields left until then
+    pending_restarts: Vec<(ThreadId, usize)>,
 }
 
-pub struct InitSystem {
-    bi_mngr: BootInfoManager,
-    thread_infos: Vec<ThreadInfo>,
+/// Entry point of the root task: sets up the system and then handles
+/// the messages on the global fault endpoint forever
+pub fn run(bootinfo: &'static seL4_BootInfo) -> ! {
+    let mut init_sys = InitSystem::new(bootinfo);
+
+    let fault_ep = match init_sys.init() {
+        Ok(fault_ep) => fault_ep,
+        Err(e) => {
+            debug_println!("root-task failed to initialize the system: {}", e);
+            loop {
+                unsafe { seL4_Yield() };
+            }
+        }
+    };
+
+    loop {
+        if let Err(e) = init_sys.wait_message(fault_ep) {
+            debug_println!("root-task failed to handle message: {}", e);
+        }
+    }
 }
 
 impl InitSystem {
@@ -40,244 +88,401 @@ impl InitSystem {
     pub fn new(bootinfo: &'static seL4_BootInfo) -> InitSystem {
         InitSystem {
             bi_mngr: BootInfoManager::new(bootinfo),
-            thread_infos: Vec::new(),
+            fault_ep_cap: seL4_CapNull as _,
+            badges: BadgeAllocator::new(),
+            threads: ThreadRegistry::new(),
+            exit_codes: VecDeque::new(),
+            fault_handler: None,
+            pending_restarts: Vec::new(),
         }
     }
 
-    /// Returns cap to global fault endpoint if one is created/used
-    pub fn init(&mut self) -> Option<seL4_CPtr> {
+    /// Returns cap to the global fault endpoint
+    pub fn init(&mut self) -> Result<seL4_CPtr, Error> {
         self.bi_mngr.debug_print_bootinfo();
 
-        let global_fault_ep_cap = self.create_ep();
+        self.fault_ep_cap = self.create_ep()?;
 
-        self.create_thread(
-            global_fault_ep_cap,
-            thread_b::FAULT_EP_BADGE,
-            thread_b::IPC_EP_BADGE,
-            thread_b::IPC_BUFFER_VADDR,
-            None,
-            thread_b::run,
-        );
+        let fault_ep_cap = self.fault_ep_cap;
+
+        let thread_b = ThreadBuilder::new(thread_b::run)
+            .name("thread_b")
+            .fault_ep(fault_ep_cap)
+            .ipc_ep()
+            .spawn(self)?;
+
+        // give thread A access to thread B's IPC ep
+        let thread_a = ThreadBuilder::new(thread_a::run)
+            .name("thread_a")
+            .fault_ep(fault_ep_cap)
+            .arg(thread_b.ipc_ep_cap().unwrap())
+            .spawn(self)?;
 
-        let thread_b_ipc_ep_cap = self
-            .thread_infos
-            .iter()
-            .find(|t| t.ipc_ep_badge == thread_b::IPC_EP_BADGE)
-            .unwrap()
-            .ipc_ep_cap;
-
-        self.create_thread(
-            global_fault_ep_cap,
-            thread_a::FAULT_EP_BADGE,
-            thread_a::IPC_EP_BADGE,
-            thread_a::IPC_BUFFER_VADDR,
-            Some(thread_b_ipc_ep_cap), // give thread A access to thread B's IPC ep
-            thread_a::run,
+        // both threads exist before either runs
+        thread_b.start()?;
+        thread_a.start()?;
+
+        debug_println!(
+            "untyped memory remaining = {} bytes\n",
+            self.bi_mngr.untyped_free_bytes()
         );
 
-        self.start_threads();
+        Ok(fault_ep_cap)
+    }
 
-        Some(global_fault_ep_cap)
+    /// Cap to the global fault endpoint the root task waits on,
+    /// for use with ThreadBuilder::fault_ep
+    pub fn fault_ep_cap(&self) -> seL4_CPtr {
+        self.fault_ep_cap
     }
 
-    pub fn is_fault(&self, badge: seL4_Word) -> bool {
-        for ref thread in self.thread_infos.iter() {
-            if thread.fault_ep_badge == badge {
-                return true;
+    /// Waits for a message on the fault endpoint and handles it. While
+    /// restarts are pending the endpoint is polled and the root task
+    /// yields between polls, counting down the restarts' backoff
+    pub fn wait_message(&mut self, fault_ep: seL4_CPtr) -> Result<(), Error> {
+        let mut badge: seL4_Word = 0;
+
+        if self.pending_restarts.is_empty() {
+            let msg_info = unsafe { seL4_Recv(fault_ep, &mut badge) };
+            return self.handle_message(badge, msg_info);
+        }
+
+        let msg_info = unsafe { seL4_NBRecv(fault_ep, &mut badge) };
+
+        // senders on the endpoint are all badged, so no badge means no message
+        if badge != 0 {
+            return self.handle_message(badge, msg_info);
+        }
+
+        unsafe { seL4_Yield() };
+        self.poll_restarts()
+    }
+
+    /// Counts down the backoff of pending restarts by one yield
+    /// and restarts the threads whose backoff is over
+    fn poll_restarts(&mut self) -> Result<(), Error> {
+        for &mut (_, ref mut yields) in self.pending_restarts.iter_mut() {
+            *yields -= 1;
+        }
+
+        while let Some(idx) = self.pending_restarts.iter().position(|&(_, y)| y == 0) {
+            let (id, _) = self.pending_restarts.remove(idx);
+
+            // killed or halted in the meantime
+            if let Some(info) = self.threads.get_mut(id) {
+                thread::restart(info)?;
             }
         }
 
-        false
+        Ok(())
     }
 
-    pub fn handle_fault(&self, badge: seL4_Word) {
-        debug_println!("!!! thread faulted - badge = 0x{:X} !!!\n", badge);
-        unsafe { seL4_DebugDumpScheduler() };
-        debug_println!("");
+    /// Handles a message received on the fault endpoint, either a thread
+    /// exiting, a thread faulting or a notification
+    pub fn handle_message(
+        &mut self,
+        badge: seL4_Word,
+        msg_info: seL4_MessageInfo_t,
+    ) -> Result<(), Error> {
+        let label = unsafe { seL4_MessageInfo_get_label(msg_info) } as seL4_Word;
+
+        match badge::classify(badge) {
+            Some(BadgeKind::Fault) if label == EXIT_LABEL => self.handle_exit(badge),
+            Some(BadgeKind::Fault) => {
+                let fault = Fault::decode(msg_info);
+
+                if self.handle_lazy_fault(&fault) {
+                    return Ok(());
+                }
+
+                self.handle_fault(badge, fault)
+            }
+            Some(BadgeKind::Notification) => {
+                debug_println!("\nroot-task got notification - badge 0x{:X}", badge);
+                Ok(())
+            }
+            Some(BadgeKind::Ipc) | None => Err(Error::UnexpectedBadge { badge }),
+        }
     }
 
-    fn create_ep(&mut self) -> seL4_CPtr {
-        let untyped_cap = self
-            .bi_mngr
-            .get_untyped(None, 1 << seL4_EndpointBits)
-            .unwrap();
+    /// Issues a badge from the namespace for minting caps outside of
+    /// ThreadBuilder, i.e. notification bits bound to the fault endpoint
+    pub fn alloc_badge(&mut self, kind: BadgeKind) -> Result<seL4_Word, Error> {
+        self.badges.alloc(kind)
+    }
 
-        let ep_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
+    /// Returns a badge from alloc_badge, fails if it is not in use
+    pub fn free_badge(&mut self, badge: seL4_Word) -> Result<(), Error> {
+        self.badges.free(badge)
+    }
 
-        let err = self.bi_mngr.untyped_retype_root(
-            untyped_cap,
-            api_object_seL4_EndpointObject,
-            seL4_EndpointBits as _,
-            ep_cap,
-        );
-        assert!(err == 0, "Failed to retype untyped memory");
+    /// Namespace of a received badge, None for unbadged messages
+    pub fn classify_badge(&self, badge: seL4_Word) -> Option<BadgeKind> {
+        badge::classify(badge)
+    }
 
-        ep_cap
+    /// Reserves [vaddr, vaddr + size) as a demand paged region, pages are
+    /// mapped with flags when a thread first touches them
+    pub fn register_lazy_region(
+        &mut self,
+        vaddr: seL4_Word,
+        size: usize,
+        flags: MapFlags,
+    ) -> Result<(), Error> {
+        self.bi_mngr.reserve_lazy_region(vaddr, size, flags)
+    }
+
+    /// Reserves a free demand paged region of size bytes, i.e. for a
+    /// thread's heap, returns its start
+    pub fn alloc_lazy_region(&mut self, size: usize, flags: MapFlags) -> Result<seL4_Word, Error> {
+        self.bi_mngr.alloc_lazy_region(size, flags)
     }
 
-    fn start_threads(&mut self) {
-        for ref thread in self.thread_infos.iter() {
-            let err = unsafe { seL4_TCB_Resume(thread.tcb_cap) };
-            assert!(err == 0, "Failed to resume TCB");
+    /// Releases a demand paged region and unmaps the pages mapped in it,
+    /// no thread may use it anymore
+    pub fn free_lazy_region(&mut self, vaddr: seL4_Word) -> Result<(), Error> {
+        self.bi_mngr.free_vaddr(vaddr)
+    }
+
+    /// Maps a buffer of at least size bytes for bulk transfers between
+    /// threads, threads share the root task's VSpace so both ends can use it
+    pub fn alloc_shared_buffer(&mut self, size: usize) -> Result<ipc::SharedBuffer, Error> {
+        let page_size: usize = 1 << CurrentArch::PAGE_BITS;
+        let size = (size + page_size - 1) & !(page_size - 1);
+
+        let vaddr = self.bi_mngr.alloc_vaddr(size, CurrentArch::PAGE_BITS)?;
+        if let Err(e) = self.bi_mngr.map_region(vaddr, size, MapFlags::data()) {
+            self.bi_mngr.free_vaddr(vaddr)?;
+            return Err(e);
         }
+
+        Ok(unsafe { ipc::SharedBuffer::from_raw(vaddr, size) })
     }
 
-    /// Create thread, does not start the thread
-    fn create_thread(
-        &mut self,
-        fault_ep_cap: seL4_CPtr,
-        fault_ep_badge: seL4_Word,
-        ipc_ep_badge: seL4_Word,
-        ipc_buffer_vaddr: seL4_Word,
-        run_fn_ipc_ep_cap: Option<seL4_CPtr>,
-        run_fn: fn(seL4_CPtr),
-    ) {
-        let cspace_cap = seL4_CapInitThreadCNode;
-        let pd_cap = seL4_CapInitThreadVSpace;
-
-        // untyped large enough for:
-        // - thread TCB
-        // - IPC frame
-        // - badged endpoint (provided to thread as fault ep)
-        let untyped_size_bytes =
-            (1 << seL4_TCBBits) + (1 << seL4_PageBits) + (1 << seL4_EndpointBits);
-
-        let untyped_cap = self.bi_mngr.get_untyped(None, untyped_size_bytes).unwrap();
-
-        let tcb_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
-        let ipc_frame_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
-        let badged_fault_ep_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
-        let ipc_ep_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
-        let badged_ipc_ep_cap = self.bi_mngr.get_next_free_cap_slot().unwrap();
-
-        let err = self.bi_mngr.untyped_retype_root(
-            untyped_cap,
-            api_object_seL4_TCBObject,
-            seL4_TCBBits as _,
-            tcb_cap,
-        );
-        assert!(err == 0, "Failed to retype untyped memory");
+    /// Unmaps a buffer from alloc_shared_buffer, no thread may use it anymore
+    pub fn free_shared_buffer(&mut self, shared: ipc::SharedBuffer) -> Result<(), Error> {
+        self.bi_mngr.unmap_region(shared.vaddr(), shared.size())?;
+        self.bi_mngr.free_vaddr(shared.vaddr())
+    }
 
-        let err = self.bi_mngr.untyped_retype_root(
-            untyped_cap,
-            _object_seL4_ARM_SmallPageObject,
-            seL4_PageBits as _,
-            ipc_frame_cap,
-        );
-        assert!(err == 0, "Failed to retype untyped memory");
+    /// Empty slot in the root task's CSpace, which all threads share,
+    /// i.e. for a thread to receive caps in with ipc::set_receive_slot
+    pub fn alloc_slot(&mut self) -> Result<seL4_CPtr, Error> {
+        self.bi_mngr.alloc_slot()
+    }
 
-        let err = self.bi_mngr.untyped_retype_root(
-            untyped_cap,
-            api_object_seL4_EndpointObject,
-            seL4_EndpointBits as _,
-            ipc_ep_cap,
-        );
-        assert!(err == 0, "Failed to retype untyped memory");
-
-        // map the frame into the vspace at ipc_buffer_vaddr
-        let err = self
-            .bi_mngr
-            .map_paddr(untyped_cap, ipc_frame_cap, ipc_buffer_vaddr);
-        assert!(err == 0, "Failed to map IPC frame");
-
-        // set the IPC buffer's virtual address in a field of the IPC buffer
-        let ipc_buffer: *mut seL4_IPCBuffer = ipc_buffer_vaddr as _;
-        unsafe { (*ipc_buffer).userData = ipc_buffer_vaddr };
-
-        // mint a copy of the endpoint cap into our cspace
-        let err: seL4_Error = unsafe {
-            seL4_CNode_Mint(
-                cspace_cap,
-                badged_fault_ep_cap,
-                seL4_WordBits as _,
-                cspace_cap,
-                fault_ep_cap,
-                seL4_WordBits as _,
-                seL4_CapRights_new(1, 1, 1),
-                fault_ep_badge,
-            )
-        };
-        assert!(err == 0, "Failed to mint a copy of the fault endpoint");
-
-        let err: seL4_Error = unsafe {
-            seL4_CNode_Mint(
-                cspace_cap,
-                badged_ipc_ep_cap,
-                seL4_WordBits as _,
-                cspace_cap,
-                ipc_ep_cap,
-                seL4_WordBits as _,
-                seL4_CapRights_new(1, 1, 1),
-                ipc_ep_badge,
-            )
-        };
-        assert!(err == 0, "Failed to mint a copy of the IPC endpoint");
-
-        let err: seL4_Error = unsafe {
-            seL4_TCB_Configure(
-                tcb_cap,
-                badged_fault_ep_cap,
-                cspace_cap.into(),
-                seL4_NilData.into(),
-                pd_cap.into(),
-                seL4_NilData.into(),
-                ipc_buffer_vaddr,
-                ipc_frame_cap,
-            )
+    /// Deletes the cap in the slot, if any, and releases the slot
+    pub fn free_slot(&mut self, slot_cap: seL4_CPtr) -> Result<(), Error> {
+        self.bi_mngr.delete_cap(slot_cap)
+    }
+
+    /// Reserves count slots for a server thread to save reply caps in,
+    /// fails for a count of 0 or more than ReplyCapPool::MAX_SLOTS
+    pub fn alloc_reply_cap_pool(&mut self, count: usize) -> Result<ipc::ReplyCapPool, Error> {
+        ipc::ReplyCapPool::check_count(count)?;
+
+        let first_slot_cap = self.bi_mngr.alloc_slot_range(count)?;
+        unsafe { ipc::ReplyCapPool::from_raw(first_slot_cap, count) }
+    }
+
+    /// Releases the slots of a pool, deleting reply caps still saved in them
+    pub fn free_reply_cap_pool(&mut self, pool: ipc::ReplyCapPool) -> Result<(), Error> {
+        for i in 0..pool.count() {
+            let slot_cap = pool.first_slot_cap() + i as seL4_CPtr;
+            let err: seL4_Error = unsafe {
+                seL4_CNode_Delete(seL4_CapInitThreadCNode as _, slot_cap, seL4_WordBits as _)
+            };
+            Error::check(Operation::CNodeDelete, slot_cap, err)?;
+        }
+
+        self.bi_mngr
+            .free_slot_range(pool.first_slot_cap(), pool.count());
+
+        Ok(())
+    }
+
+    /// Maps a page for a VM fault in a lazy region and replies to the
+    /// faulting thread to resume it, returns false if the fault is not
+    /// one to page in or no memory is left to do so
+    fn handle_lazy_fault(&mut self, fault: &Fault) -> bool {
+        let addr = match *fault {
+            Fault::VMFault { addr, .. } => addr,
+            _ => return false,
         };
-        assert!(err == 0, "Failed to configure TCB");
 
-        let stack_alignment_requirement: usize = (seL4_WordBits as usize / 8) * 2;
+        match self.bi_mngr.map_lazy_page(addr) {
+            Ok(true) => {
+                // an empty reply resumes the thread at the faulting instruction
+                unsafe { seL4_Reply(seL4_MessageInfo_new(0, 0, 0, 0)) };
+                true
+            }
+            Ok(false) => false,
+            Err(e) => {
+                debug_println!("failed to page in 0x{:X}: {}", addr, e);
+                false
+            }
+        }
+    }
 
-        assert!(THREAD_STACK_SIZE >= 512, "Thread stack size is too small");
-        assert!(
-            THREAD_STACK_SIZE % stack_alignment_requirement == 0,
-            "Thread stack is not properly aligned to a {} byte boundary",
-            stack_alignment_requirement
+    /// Records the exit code of the thread that returned and tears it down,
+    /// fails for a fault badge of no registered thread
+    fn handle_exit(&mut self, badge: seL4_Word) -> Result<(), Error> {
+        let exit_code = unsafe { seL4_GetMR(0) };
+
+        let id = self
+            .threads
+            .find_by_badge(badge)
+            .ok_or(Error::UnexpectedBadge { badge })?;
+        let info = self.threads.remove(id).unwrap();
+
+        debug_println!(
+            "thread '{}' exited - badge = 0x{:X} - exit code = {}",
+            info.name,
+            badge,
+            exit_code
         );
 
-        // create the thread's stack from the global allocator, which is backed by
-        // a static array, just leak from box since it won't be given back
-        let thread_stack_box: Box<u64> = Box::new((THREAD_STACK_SIZE / 8) as u64);
-        let stack_base: &'static mut u64 = Box::leak(thread_stack_box);
-        let stack_top = stack_base as *const _ as usize + THREAD_STACK_SIZE;
+        self.record_exit_code(id, Ok(exit_code));
 
-        assert!(
-            stack_top % stack_alignment_requirement == 0,
-            "Thread stack is not properly aligned to a {} byte boundary",
-            stack_alignment_requirement
-        );
+        thread::teardown(&mut self.bi_mngr, &mut self.badges, info)
+    }
 
-        let mut regs: seL4_UserContext = unsafe { mem::zeroed() };
+    pub fn is_fault(&self, badge: seL4_Word) -> bool {
+        badge::classify(badge) == Some(BadgeKind::Fault)
+    }
+
+    /// Thread whose faults are delivered with the badge
+    pub fn thread_by_badge(&self, badge: seL4_Word) -> Option<ThreadHandle> {
+        let id = self.threads.find_by_badge(badge)?;
+        self.threads.get(id).map(|info| info.handle(id))
+    }
 
-        #[allow(const_err)]
-        {
-            regs.pc = run_fn as _;
+    /// Most recently spawned thread with the name
+    pub fn thread_by_name(&self, name: &str) -> Option<ThreadHandle> {
+        let id = self.threads.find_by_name(name)?;
+        self.threads.get(id).map(|info| info.handle(id))
+    }
 
-            // badged IPC ep cap in r0 is the function parameter
-            if let Some(ipc_ep_arg) = run_fn_ipc_ep_cap {
-                regs.r0 = ipc_ep_arg as _;
-            } else {
-                regs.r0 = badged_ipc_ep_cap as _;
+    /// Registers a callback run for every fault of a thread using the
+    /// global fault endpoint, replacing the default of printing the fault
+    pub fn set_fault_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(ThreadHandle, &Fault) + 'static,
+    {
+        self.fault_handler = Some(Box::new(handler));
+    }
+
+    /// Reports the fault to the fault handler and applies the
+    /// thread's fault policy, fails for a fault badge of no registered thread
+    pub fn handle_fault(&mut self, badge: seL4_Word, fault: Fault) -> Result<(), Error> {
+        let thread = match self.thread_by_badge(badge) {
+            Some(thread) => thread,
+            None => {
+                debug_println!("fault from unknown badge 0x{:X}\n{}\n", badge, fault);
+                return Err(Error::UnexpectedBadge { badge });
+            }
+        };
+        let id = thread.id();
+
+        if let Some(ref mut handler) = self.fault_handler {
+            handler(thread, &fault);
+        } else {
+            debug_println!(
+                "!!! thread '{}' faulted - badge = 0x{:X} !!!\n{}\n",
+                thread.name(),
+                badge,
+                fault
+            );
+            unsafe { seL4_DebugDumpScheduler() };
+            debug_println!("");
+        }
+
+        let (fault_policy, num_restarts) = {
+            let info = self.threads.get(id).unwrap();
+            (info.fault_policy, info.num_restarts)
+        };
+
+        match fault_policy {
+            FaultPolicy::Kill => self.kill(id, fault),
+            FaultPolicy::Restart {
+                max_restarts,
+                backoff_yields,
+            } => {
+                if num_restarts >= max_restarts {
+                    debug_println!("thread '{}' is out of restarts, killing it", thread.name());
+                    return self.kill(id, fault);
+                }
+
+                debug_println!(
+                    "restarting thread '{}' - restart {} of {}",
+                    thread.name(),
+                    num_restarts + 1,
+                    max_restarts
+                );
+
+                // the thread stays blocked on its fault until restarted
+                let yields = (num_restarts + 1) * backoff_yields;
+                if yields == 0 {
+                    thread::restart(self.threads.get_mut(id).unwrap())
+                } else {
+                    self.pending_restarts.push((id, yields));
+                    Ok(())
+                }
+            }
+            FaultPolicy::Suspend => {
+                let info = self.threads.get_mut(id).unwrap();
+                info.suspended_fault = Some(fault);
+                thread::suspend(info)
             }
+            FaultPolicy::Halt => self.halt(),
+        }
+    }
+
+    /// Keeps the exit code for ThreadHandle::join, dropping the oldest
+    /// one if MAX_EXIT_CODES are kept already
+    fn record_exit_code(&mut self, id: ThreadId, exit_code: Result<seL4_Word, Fault>) {
+        if self.exit_codes.len() == MAX_EXIT_CODES {
+            self.exit_codes.pop_front();
         }
+        self.exit_codes.push_back((id, exit_code));
+    }
 
-        regs.sp = stack_top as seL4_Word;
+    fn take_exit_code(&mut self, id: ThreadId) -> Option<Result<seL4_Word, Fault>> {
+        let idx = self.exit_codes.iter().position(|&(i, _)| i == id)?;
+        self.exit_codes.remove(idx).map(|(_, exit_code)| exit_code)
+    }
+
+    /// Tears down the faulted thread, joining it returns the fault
+    fn kill(&mut self, id: ThreadId, fault: Fault) -> Result<(), Error> {
+        let info = self.threads.remove(id).unwrap();
+
+        debug_println!("killing thread '{}'", info.name);
+
+        self.record_exit_code(id, Err(fault));
+
+        thread::teardown(&mut self.bi_mngr, &mut self.badges, info)
+    }
 
-        // using pc, sp, (cpsr) and r0
-        let context_size = 4;
-        let err = unsafe { seL4_TCB_WriteRegisters(tcb_cap, 0, 0, context_size, &mut regs) };
-        assert!(err == 0, "Failed to write TCB registers");
+    /// Suspends every thread and then the root task itself
+    fn halt(&self) -> ! {
+        debug_println!("halting the system");
 
-        let err = unsafe { seL4_TCB_SetPriority(tcb_cap, seL4_CapInitThreadTCB.into(), 255) };
-        assert!(err == 0, "Failed to set TCB priority");
+        for (_, info) in self.threads.iter() {
+            let _ = thread::suspend(info);
+        }
+
+        #[cfg(feature = "KernelDebugBuild")]
+        unsafe {
+            seL4_DebugHalt()
+        };
+
+        loop {
+            unsafe { seL4_TCB_Suspend(seL4_CapInitThreadTCB.into()) };
+        }
+    }
 
-        self.thread_infos.push(ThreadInfo {
-            tcb_cap,
-            fault_ep_cap: badged_fault_ep_cap,
-            fault_ep_badge,
-            ipc_ep_cap: badged_ipc_ep_cap,
-            ipc_ep_badge,
-        });
+    fn create_ep(&mut self) -> Result<seL4_CPtr, Error> {
+        self.bi_mngr
+            .alloc_object(api_object_seL4_EndpointObject, seL4_EndpointBits as _)
     }
 }
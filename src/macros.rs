@@ -12,3 +12,138 @@ macro_rules! debug_println {
     ($fmt:expr) => (debug_print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => (debug_print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+/// Declares an IPC interface: the labels of its methods, numbered from 1
+/// in order, a client calling the methods over an endpoint and a server
+/// trait whose `register` adds a handler for each method to an ipc::Server.
+/// Arguments and return values are passed in a message register each.
+///
+/// ```ignore
+/// declare_interface! {
+///     /// Echo service
+///     pub interface Echo {
+///         client: EchoClient,
+///         labels: EchoLabel,
+///         fn echo(value: seL4_Word) -> seL4_Word;
+///     }
+/// }
+///
+/// // client thread
+/// let value = EchoClient::new(ep_cap).echo(42)?;
+///
+/// // server thread, with `impl Echo for EchoState`
+/// EchoState::register(Server::new(ep_cap, EchoState)).run();
+/// ```
+macro_rules! declare_interface {
+    (@one $arg:ident) => {
+        1
+    };
+    (
+        $(#[$attr:meta])*
+        pub interface $server:ident {
+            client: $client:ident,
+            labels: $labels:ident,
+            $(
+                $(#[$method_attr:meta])*
+                fn $method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;
+            )*
+        }
+    ) => {
+        /// Message labels of the interface's methods
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        pub enum $labels {
+            $($method,)*
+        }
+
+        #[allow(dead_code)]
+        impl $labels {
+            pub fn label(&self) -> $crate::sel4_sys::seL4_Word {
+                *self as $crate::sel4_sys::seL4_Word + 1
+            }
+
+            pub fn from_label(label: $crate::sel4_sys::seL4_Word) -> Option<$labels> {
+                $(
+                    if label == $labels::$method.label() {
+                        return Some($labels::$method);
+                    }
+                )*
+                None
+            }
+        }
+
+        $(#[$attr])*
+        pub trait $server {
+            $(
+                $(#[$method_attr])*
+                fn $method(
+                    &mut self,
+                    client_badge: $crate::sel4_sys::seL4_Word,
+                    $($arg: $ty),*
+                ) -> $ret;
+            )*
+
+            /// Adds a handler for every method of the interface to the server
+            fn register(server: $crate::ipc::Server<Self>) -> $crate::ipc::Server<Self>
+            where
+                Self: Sized + 'static,
+            {
+                server
+                $(
+                    .handle_raw(
+                        $labels::$method.label(),
+                        |state: &mut Self, received: &$crate::ipc::Received| {
+                            let label = $labels::$method.label();
+                            received.expect(label, 0 $(+ declare_interface!(@one $arg))*)?;
+
+                            #[allow(unused_mut, unused_variables)]
+                            let mut words = (0..received.length())
+                                .map(|i| received.word(i).unwrap());
+                            $(
+                                let $arg = <$ty as $crate::ipc::MessageWord>::from_word(
+                                    words.next().unwrap(),
+                                );
+                            )*
+
+                            let ret = state.$method(received.badge(), $($arg),*);
+
+                            let ret = $crate::ipc::MessageWord::into_word(ret);
+                            Ok($crate::ipc::load_words(label, &[ret]))
+                        },
+                    )
+                )*
+            }
+        }
+
+        /// Calls the interface's methods on a server over an endpoint
+        #[derive(Debug, Copy, Clone)]
+        pub struct $client {
+            ep_cap: $crate::sel4_sys::seL4_CPtr,
+        }
+
+        #[allow(dead_code)]
+        impl $client {
+            pub fn new(ep_cap: $crate::sel4_sys::seL4_CPtr) -> $client {
+                $client { ep_cap }
+            }
+
+            pub fn ep_cap(&self) -> $crate::sel4_sys::seL4_CPtr {
+                self.ep_cap
+            }
+
+            $(
+                $(#[$method_attr])*
+                pub fn $method(&self, $($arg: $ty),*) -> Result<$ret, $crate::Error> {
+                    let label = $labels::$method.label();
+                    let args: &[$crate::sel4_sys::seL4_Word] =
+                        &[$($crate::ipc::MessageWord::into_word($arg)),*];
+
+                    let reply = $crate::ipc::call_words(self.ep_cap, label, args);
+                    reply.expect(label, 1)?;
+
+                    Ok(<$ret as $crate::ipc::MessageWord>::from_word(reply.word(0).unwrap()))
+                }
+            )*
+        }
+    };
+}
@@ -0,0 +1,175 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use badge::{self, BadgeKind};
+use error::Error;
+use sel4_sys::seL4_Word;
+use thread::ThreadInfo;
+
+/// Index of a thread's record in the ThreadRegistry, the generation tells
+/// apart threads that reused the record of an earlier thread
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId {
+    index: usize,
+    generation: usize,
+}
+
+struct Entry {
+    generation: usize,
+    info: Option<ThreadInfo>,
+}
+
+/// Slab of thread records indexed by ThreadId, with lookup by fault badge and name.
+/// Fault badges are counters from the BadgeAllocator, so badge lookup indexes
+/// a table by the badge's value. Name lookup is O(log n), alloc has no hashed map
+pub struct ThreadRegistry {
+    entries: Vec<Entry>,
+    /// indices of entries without a thread
+    free: Vec<usize>,
+    /// indexed by the value bits of the fault badge
+    by_badge: Vec<Option<ThreadId>>,
+    /// threads sharing a name in spawn order
+    by_name: BTreeMap<&'static str, Vec<ThreadId>>,
+}
+
+impl ThreadRegistry {
+    pub fn new() -> ThreadRegistry {
+        ThreadRegistry {
+            entries: Vec::new(),
+            free: Vec::new(),
+            by_badge: Vec::new(),
+            by_name: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the thread's record, a later thread with the same name
+    /// shadows the earlier one in find_by_name until it is removed.
+    /// Fails if the thread's fault badge does not pass check_badge
+    pub fn insert(&mut self, info: ThreadInfo) -> Result<ThreadId, Error> {
+        let badge_slot = match info.fault_ep_badge {
+            Some(badge) => Some(self.check_badge(badge)?),
+            None => None,
+        };
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(Entry {
+                    generation: 0,
+                    info: None,
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        let id = ThreadId {
+            index,
+            generation: self.entries[index].generation,
+        };
+
+        if let Some(slot) = badge_slot {
+            if slot >= self.by_badge.len() {
+                self.by_badge.resize(slot + 1, None);
+            }
+            self.by_badge[slot] = Some(id);
+        }
+        self.by_name
+            .entry(info.name)
+            .or_insert_with(Vec::new)
+            .push(id);
+
+        self.entries[index].info = Some(info);
+
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: ThreadId) -> Option<ThreadInfo> {
+        let info = {
+            let entry = self.entries.get_mut(id.index)?;
+            if entry.generation != id.generation {
+                return None;
+            }
+            entry.generation += 1;
+            entry.info.take()?
+        };
+
+        self.free.push(id.index);
+
+        if let Some(slot) = info.fault_ep_badge.and_then(badge_slot) {
+            self.by_badge[slot] = None;
+        }
+
+        let name_unused = match self.by_name.get_mut(info.name) {
+            Some(ids) => {
+                ids.retain(|&other| other != id);
+                ids.is_empty()
+            }
+            None => false,
+        };
+        if name_unused {
+            self.by_name.remove(info.name);
+        }
+
+        Some(info)
+    }
+
+    pub fn get(&self, id: ThreadId) -> Option<&ThreadInfo> {
+        match self.entries.get(id.index) {
+            Some(entry) if entry.generation == id.generation => entry.info.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: ThreadId) -> Option<&mut ThreadInfo> {
+        match self.entries.get_mut(id.index) {
+            Some(entry) if entry.generation == id.generation => entry.info.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// Checks the fault badge can be registered: it is from the fault
+    /// namespace and no other thread has it. Returns its badge table index
+    pub fn check_badge(&self, badge: seL4_Word) -> Result<usize, Error> {
+        let slot = badge_slot(badge).ok_or(Error::InvalidBadge { badge })?;
+
+        match self.by_badge.get(slot) {
+            Some(&Some(_)) => Err(Error::BadgeInUse { badge }),
+            _ => Ok(slot),
+        }
+    }
+
+    /// Thread whose faults are delivered with the badge
+    pub fn find_by_badge(&self, badge: seL4_Word) -> Option<ThreadId> {
+        let slot = badge_slot(badge)?;
+        self.by_badge.get(slot).cloned().and_then(|id| id)
+    }
+
+    /// Most recently inserted thread with the name
+    pub fn find_by_name(&self, name: &str) -> Option<ThreadId> {
+        self.by_name.get(name).and_then(|ids| ids.last()).cloned()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (ThreadId, &'a ThreadInfo)> + 'a {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                entry.info.as_ref().map(|info| {
+                    (
+                        ThreadId {
+                            index,
+                            generation: entry.generation,
+                        },
+                        info,
+                    )
+                })
+            })
+    }
+}
+
+/// Index of a fault badge in the badge table, None for other badges
+fn badge_slot(badge: seL4_Word) -> Option<usize> {
+    match badge::classify(badge) {
+        Some(BadgeKind::Fault) => Some(badge::value(badge) as usize),
+        _ => None,
+    }
+}
@@ -0,0 +1,131 @@
+use alloc::vec::Vec;
+use core::mem;
+use error::Error;
+use sel4_sys::{seL4_CPtr, seL4_SlotRegion};
+
+const BITS_PER_WORD: usize = mem::size_of::<usize>() * 8;
+
+/// Bitmap backed allocator over a region of empty CSpace slots,
+/// a set bit marks a slot as in use
+pub struct SlotAllocator {
+    start: seL4_CPtr,
+    num_slots: usize,
+    num_used: usize,
+    bitmap: Vec<usize>,
+    /// index of the first bitmap word that may contain a free slot
+    first_free_word: usize,
+}
+
+impl SlotAllocator {
+    pub fn new(region: seL4_SlotRegion) -> SlotAllocator {
+        let num_slots = (region.end - region.start) as usize;
+        let num_words = (num_slots + BITS_PER_WORD - 1) / BITS_PER_WORD;
+
+        let mut bitmap = Vec::with_capacity(num_words);
+        bitmap.resize(num_words, 0);
+
+        // mark the bits past the end of the region as used so they are never handed out
+        let tail_bits = num_slots % BITS_PER_WORD;
+        if tail_bits != 0 {
+            bitmap[num_words - 1] = !0 << tail_bits;
+        }
+
+        SlotAllocator {
+            start: region.start,
+            num_slots,
+            num_used: 0,
+            bitmap,
+            first_free_word: 0,
+        }
+    }
+
+    pub fn num_free(&self) -> usize {
+        self.num_slots - self.num_used
+    }
+
+    pub fn alloc(&mut self) -> Result<seL4_CPtr, Error> {
+        for word_idx in self.first_free_word..self.bitmap.len() {
+            let word = self.bitmap[word_idx];
+            if word != !0 {
+                let bit = (!word).trailing_zeros() as usize;
+                self.bitmap[word_idx] |= 1 << bit;
+                self.num_used += 1;
+                self.first_free_word = word_idx;
+
+                return Ok(self.start + (word_idx * BITS_PER_WORD + bit) as seL4_CPtr);
+            }
+        }
+
+        self.first_free_word = self.bitmap.len();
+
+        Err(Error::SlotsExhausted { count: 1 })
+    }
+
+    /// Allocates `count` contiguous slots, returns the first slot,
+    /// suitable for retyping multiple objects in one invocation
+    pub fn alloc_range(&mut self, count: usize) -> Result<seL4_CPtr, Error> {
+        assert!(count != 0, "Cannot allocate an empty slot range");
+
+        let mut run_start = self.first_free_word * BITS_PER_WORD;
+        let mut run_len = 0;
+
+        for idx in run_start..self.num_slots {
+            if self.is_used(idx) {
+                run_start = idx + 1;
+                run_len = 0;
+                continue;
+            }
+
+            run_len += 1;
+            if run_len == count {
+                for used_idx in run_start..(run_start + count) {
+                    self.set_used(used_idx, true);
+                }
+                self.num_used += count;
+
+                return Ok(self.start + run_start as seL4_CPtr);
+            }
+        }
+
+        Err(Error::SlotsExhausted { count })
+    }
+
+    /// Returns a slot to the allocator, the slot must already be empty
+    /// (see BootInfoManager::delete_cap)
+    pub fn free(&mut self, slot: seL4_CPtr) {
+        self.free_range(slot, 1);
+    }
+
+    pub fn free_range(&mut self, first_slot: seL4_CPtr, count: usize) {
+        assert!(
+            first_slot >= self.start
+                && (first_slot - self.start) as usize + count <= self.num_slots,
+            "Slot range is not managed by this allocator"
+        );
+
+        let first_idx = (first_slot - self.start) as usize;
+        for idx in first_idx..(first_idx + count) {
+            assert!(self.is_used(idx), "Slot is not allocated");
+            self.set_used(idx, false);
+        }
+        self.num_used -= count;
+
+        let word_idx = first_idx / BITS_PER_WORD;
+        if word_idx < self.first_free_word {
+            self.first_free_word = word_idx;
+        }
+    }
+
+    fn is_used(&self, idx: usize) -> bool {
+        self.bitmap[idx / BITS_PER_WORD] & (1 << (idx % BITS_PER_WORD)) != 0
+    }
+
+    fn set_used(&mut self, idx: usize, used: bool) {
+        let mask = 1 << (idx % BITS_PER_WORD);
+        if used {
+            self.bitmap[idx / BITS_PER_WORD] |= mask;
+        } else {
+            self.bitmap[idx / BITS_PER_WORD] &= !mask;
+        }
+    }
+}
@@ -0,0 +1,773 @@
+use alloc::boxed::Box;
+use arch::{Arch, CurrentArch};
+use badge::{self, BadgeAllocator, BadgeKind};
+use bootinfo_manager::BootInfoManager;
+use core::mem::{self, ManuallyDrop};
+use core::ptr;
+use error::{Error, Operation};
+use fault::{Fault, FaultPolicy};
+use registry::ThreadId;
+use sel4_sys::*;
+use vspace::{MapFlags, Region};
+use InitSystem;
+
+/// default size of a thread's stack in bytes, stacks are rounded up to whole pages
+pub const DEFAULT_STACK_SIZE: usize = 4096;
+
+/// default priority of a spawned thread, same as the root task
+pub const DEFAULT_PRIORITY: u8 = 255;
+
+/// default max controlled priority of a spawned thread, the root task's
+/// own, which the kernel starts it with (seL4_MaxPrio)
+pub const DEFAULT_MAX_PRIORITY: u8 = 255;
+
+/// Label of the message a returning thread sends on its fault endpoint,
+/// the largest label that fits on all architectures so it never collides
+/// with a fault label
+pub const EXIT_LABEL: seL4_Word = 0xF_FFFF;
+
+/// Return values of thread entry points that are reported as exit code
+pub trait IntoExitCode {
+    fn into_exit_code(self) -> seL4_Word;
+}
+
+impl IntoExitCode for () {
+    fn into_exit_code(self) -> seL4_Word {
+        0
+    }
+}
+
+impl IntoExitCode for seL4_Word {
+    fn into_exit_code(self) -> seL4_Word {
+        self
+    }
+}
+
+/// Root task bookkeeping for a spawned thread
+pub struct ThreadInfo {
+    pub name: &'static str,
+    pub tcb_cap: seL4_CPtr,
+    pub fault_ep_cap: Option<seL4_CPtr>,
+    pub fault_ep_badge: Option<seL4_Word>,
+    /// true if faults and the exit are sent to the root task's global fault endpoint
+    pub joinable: bool,
+    /// badged copy of the thread's own endpoint handed out, the endpoint
+    /// is never deleted as other threads may hold copies, see teardown
+    pub ipc_ep_cap: Option<seL4_CPtr>,
+    pub ipc_ep_badge: Option<seL4_Word>,
+    pub ipc_buffer_vaddr: seL4_Word,
+    /// mapped stack pages, the guard page is right below stack.start
+    pub stack: Region,
+    /// allocation of the closure the thread was started with
+    pub start_alloc: Option<StartAlloc>,
+    pub fault_policy: FaultPolicy,
+    /// the fault the thread was suspended after by FaultPolicy::Suspend
+    pub suspended_fault: Option<Fault>,
+    /// creates the closure to start the thread over with, see restart
+    pub restart_entry: Option<Box<dyn Fn() -> StartData>>,
+    pub num_restarts: usize,
+}
+
+impl ThreadInfo {
+    pub fn handle(&self, id: ThreadId) -> ThreadHandle {
+        ThreadHandle {
+            id,
+            name: self.name,
+            tcb_cap: self.tcb_cap,
+            fault_ep_badge: self.fault_ep_badge,
+            joinable: self.joinable,
+            ipc_ep_cap: self.ipc_ep_cap,
+            ipc_ep_badge: self.ipc_ep_badge,
+        }
+    }
+}
+
+/// A closure boxed for a thread to take, dropped by the root task if the
+/// thread is never started
+pub struct StartData {
+    trampoline: seL4_Word,
+    ptr: *mut u8,
+    drop_fn: unsafe fn(*mut u8),
+    free_fn: unsafe fn(*mut u8),
+}
+
+impl StartData {
+    fn new<F, R>(f: F) -> StartData
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoExitCode,
+    {
+        StartData {
+            trampoline: trampoline::<F, R> as usize as seL4_Word,
+            ptr: Box::into_raw(Box::new(f)) as *mut u8,
+            drop_fn: drop_boxed::<F>,
+            free_fn: free_boxed::<F>,
+        }
+    }
+
+    /// Hands the closure over to a started thread, the root task only
+    /// keeps the allocation to free it later
+    fn into_alloc(self) -> StartAlloc {
+        let alloc = StartAlloc {
+            ptr: self.ptr,
+            free_fn: self.free_fn,
+        };
+        mem::forget(self);
+        alloc
+    }
+}
+
+impl Drop for StartData {
+    fn drop(&mut self) {
+        unsafe { (self.drop_fn)(self.ptr) }
+    }
+}
+
+/// Allocation of a closure moved out by its thread
+pub struct StartAlloc {
+    ptr: *mut u8,
+    free_fn: unsafe fn(*mut u8),
+}
+
+impl StartAlloc {
+    /// Frees the allocation, the thread must no longer be running
+    pub unsafe fn free(self) {
+        (self.free_fn)(self.ptr)
+    }
+}
+
+unsafe fn drop_boxed<F>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut F));
+}
+
+unsafe fn free_boxed<F>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut ManuallyDrop<F>));
+}
+
+/// Entry point of all threads, the closure is moved onto the thread's
+/// stack so the thread never touches the root task's allocator.
+/// Its return value is reported on exit_ep, see exit
+extern "C" fn trampoline<F, R>(data: *mut F, exit_ep: seL4_CPtr, tcb_cap: seL4_CPtr) -> !
+where
+    F: FnOnce() -> R,
+    R: IntoExitCode,
+{
+    let f = unsafe { ptr::read(data) };
+    let exit_code = f().into_exit_code();
+    exit(exit_ep, tcb_cap, exit_code)
+}
+
+/// Sends the exit code to the root task, which tears the thread down while
+/// it waits for a reply that never comes. Without an exit endpoint the
+/// thread just suspends itself
+fn exit(exit_ep: seL4_CPtr, tcb_cap: seL4_CPtr, exit_code: seL4_Word) -> ! {
+    if exit_ep != seL4_CapNull as seL4_CPtr {
+        unsafe {
+            seL4_SetMR(0, exit_code);
+            seL4_Call(exit_ep, seL4_MessageInfo_new(EXIT_LABEL as _, 0, 0, 1));
+        }
+    }
+
+    loop {
+        unsafe { seL4_TCB_Suspend(tcb_cap) };
+    }
+}
+
+enum Entry {
+    /// passed `arg` or the thread's badged IPC ep cap
+    Fn(fn(seL4_CPtr)),
+    Closure(StartData),
+}
+
+/// Handle to a thread spawned by a ThreadBuilder
+#[derive(Debug, Copy, Clone)]
+pub struct ThreadHandle {
+    id: ThreadId,
+    name: &'static str,
+    tcb_cap: seL4_CPtr,
+    fault_ep_badge: Option<seL4_Word>,
+    joinable: bool,
+    ipc_ep_cap: Option<seL4_CPtr>,
+    ipc_ep_badge: Option<seL4_Word>,
+}
+
+impl ThreadHandle {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn tcb_cap(&self) -> seL4_CPtr {
+        self.tcb_cap
+    }
+
+    /// Badge the thread's faults are delivered with, None if it has no fault endpoint
+    pub fn fault_ep_badge(&self) -> Option<seL4_Word> {
+        self.fault_ep_badge
+    }
+
+    /// Badged cap to the thread's own IPC endpoint, see ThreadBuilder::ipc_ep
+    pub fn ipc_ep_cap(&self) -> Option<seL4_CPtr> {
+        self.ipc_ep_cap
+    }
+
+    /// Runs the thread spawned by ThreadBuilder::spawn
+    pub fn start(&self) -> Result<(), Error> {
+        resume(self.tcb_cap)
+    }
+
+    /// Badge of the cap to the thread's IPC endpoint, as seen by the thread
+    pub fn ipc_ep_badge(&self) -> Option<seL4_Word> {
+        self.ipc_ep_badge
+    }
+
+    /// Waits for the thread to return and gives back its exit code, the
+    /// thread has been torn down by then. Faults and exits of other threads
+    /// are handled while waiting.
+    /// Only threads using the init system's fault endpoint can be joined,
+    /// joining a thread suspended by its fault policy fails with ThreadSuspended.
+    pub fn join(&self, init_sys: &mut InitSystem) -> Result<seL4_Word, Error> {
+        if !self.joinable {
+            return Err(Error::NotJoinable { tcb: self.tcb_cap });
+        }
+
+        loop {
+            match init_sys.take_exit_code(self.id) {
+                Some(Ok(exit_code)) => return Ok(exit_code),
+                Some(Err(fault)) => return Err(Error::ThreadKilled { fault }),
+                None => (),
+            }
+
+            match init_sys.threads.get(self.id) {
+                // gone and its exit code dropped, or joined before
+                None => return Err(Error::NotJoinable { tcb: self.tcb_cap }),
+                Some(info) => {
+                    if let Some(fault) = info.suspended_fault {
+                        return Err(Error::ThreadSuspended { fault });
+                    }
+                }
+            }
+
+            let fault_ep_cap = init_sys.fault_ep_cap;
+            init_sys.wait_message(fault_ep_cap)?;
+        }
+    }
+}
+
+/// Configures a thread in the root task's CSpace and VSpace
+///
+/// ```ignore
+/// let handle = ThreadBuilder::new(thread_b::run)
+///     .name("thread_b")
+///     .fault_ep(init_sys.fault_ep_cap())
+///     .ipc_ep()
+///     .spawn(&mut init_sys)?;
+///
+/// let cfg = WorkerArgs { ep_cap, shared_vaddr, count: 4 };
+/// let worker = ThreadBuilder::with_start_arg(worker::run, cfg).spawn(&mut init_sys)?;
+///
+/// // threads only run once started, after everything they use is set up
+/// handle.start()?;
+/// worker.start()?;
+/// ```
+pub struct ThreadBuilder {
+    entry: Entry,
+    arg: Option<seL4_Word>,
+    name: &'static str,
+    priority: u8,
+    max_priority: u8,
+    stack_size: usize,
+    ipc_buffer_vaddr: Option<seL4_Word>,
+    fault_ep: Option<seL4_CPtr>,
+    ipc_ep: bool,
+    fault_policy: FaultPolicy,
+}
+
+impl ThreadBuilder {
+    /// Thread running entry with a single word argument, see arg
+    pub fn new(entry: fn(seL4_CPtr)) -> ThreadBuilder {
+        ThreadBuilder::with_entry(Entry::Fn(entry))
+    }
+
+    /// Thread running the closure, its return value is the exit code
+    pub fn from_closure<F, R>(f: F) -> ThreadBuilder
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoExitCode,
+    {
+        ThreadBuilder::with_entry(Entry::Closure(StartData::new(f)))
+    }
+
+    /// Thread running entry with a start argument, i.e. a struct carrying
+    /// several caps, config values and shared memory pointers
+    pub fn with_start_arg<T, R>(entry: fn(T) -> R, arg: T) -> ThreadBuilder
+    where
+        T: Send + 'static,
+        R: IntoExitCode + 'static,
+    {
+        ThreadBuilder::from_closure(move || entry(arg))
+    }
+
+    fn with_entry(entry: Entry) -> ThreadBuilder {
+        ThreadBuilder {
+            entry,
+            arg: None,
+            name: "child",
+            priority: DEFAULT_PRIORITY,
+            max_priority: DEFAULT_MAX_PRIORITY,
+            stack_size: DEFAULT_STACK_SIZE,
+            ipc_buffer_vaddr: None,
+            fault_ep: None,
+            ipc_ep: false,
+            fault_policy: FaultPolicy::Suspend,
+        }
+    }
+
+    pub fn name(mut self, name: &'static str) -> ThreadBuilder {
+        self.name = name;
+        self
+    }
+
+    pub fn priority(mut self, priority: u8) -> ThreadBuilder {
+        self.priority = priority;
+        self
+    }
+
+    /// Highest priority the thread may give itself or threads it creates,
+    /// defaults to the root task's
+    pub fn max_priority(mut self, max_priority: u8) -> ThreadBuilder {
+        self.max_priority = max_priority;
+        self
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> ThreadBuilder {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// Maps the IPC buffer at the fixed page aligned vaddr instead of
+    /// a free one picked by the VSpace manager
+    pub fn ipc_buffer_vaddr(mut self, vaddr: seL4_Word) -> ThreadBuilder {
+        self.ipc_buffer_vaddr = Some(vaddr);
+        self
+    }
+
+    /// Faults are sent to ep_cap with a fault badge unique to the thread,
+    /// without a fault endpoint a faulting thread is just suspended by the kernel
+    pub fn fault_ep(mut self, ep_cap: seL4_CPtr) -> ThreadBuilder {
+        self.fault_ep = Some(ep_cap);
+        self
+    }
+
+    /// Creates an endpoint for the thread, a cap minted with an IPC badge
+    /// is passed as the entry argument unless one is given with arg
+    pub fn ipc_ep(mut self) -> ThreadBuilder {
+        self.ipc_ep = true;
+        self
+    }
+
+    /// What the init system does when the thread faults, defaults to
+    /// FaultPolicy::Suspend. Only threads created with new can be restarted
+    pub fn fault_policy(mut self, fault_policy: FaultPolicy) -> ThreadBuilder {
+        self.fault_policy = fault_policy;
+        self
+    }
+
+    /// Argument passed to the entry function given to new,
+    /// ignored for closure threads
+    pub fn arg(mut self, arg: seL4_Word) -> ThreadBuilder {
+        self.arg = Some(arg);
+        self
+    }
+
+    /// Creates the thread without starting it, see ThreadHandle::start
+    pub fn spawn(self, init_sys: &mut InitSystem) -> Result<ThreadHandle, Error> {
+        let restartable = match self.entry {
+            Entry::Fn(_) => true,
+            Entry::Closure(_) => false,
+        };
+        if let FaultPolicy::Restart { .. } = self.fault_policy {
+            if !restartable {
+                return Err(Error::NotRestartable { name: self.name });
+            }
+        }
+        if self.stack_size == 0 {
+            return Err(Error::EmptyStack { name: self.name });
+        }
+
+        let mut parts = SpawnParts::new();
+        let info = match self.create(init_sys, &mut parts) {
+            Ok(info) => info,
+            Err(e) => {
+                parts.free(&mut init_sys.bi_mngr, &mut init_sys.badges)?;
+                return Err(e);
+            }
+        };
+
+        // the badge was checked when minted, so this cannot fail
+        let id = init_sys.threads.insert(info)?;
+
+        Ok(init_sys.threads.get(id).unwrap().handle(id))
+    }
+
+    /// Sets up the thread's objects, recording each in parts as soon as it
+    /// exists so spawn can release them if a later step fails
+    fn create(
+        self,
+        init_sys: &mut InitSystem,
+        parts: &mut SpawnParts,
+    ) -> Result<ThreadInfo, Error> {
+        let cspace_cap = seL4_CapInitThreadCNode;
+        let pd_cap = seL4_CapInitThreadVSpace;
+        let bi_mngr = &mut init_sys.bi_mngr;
+
+        let tcb_cap = bi_mngr.alloc_object(api_object_seL4_TCBObject, seL4_TCBBits as _)?;
+        parts.tcb_cap = Some(tcb_cap);
+
+        let ipc_frame_cap =
+            bi_mngr.alloc_object(CurrentArch::PAGE_OBJECT, CurrentArch::PAGE_BITS)?;
+        parts.ipc_frame_cap = Some(ipc_frame_cap);
+
+        // map the frame at the requested vaddr or into a free region of the vspace
+        let ipc_buffer_vaddr = match self.ipc_buffer_vaddr {
+            Some(vaddr) => {
+                bi_mngr.reserve_vaddr(vaddr, 1 << CurrentArch::PAGE_BITS)?;
+                vaddr
+            }
+            None => bi_mngr.alloc_vaddr(1 << CurrentArch::PAGE_BITS, CurrentArch::PAGE_BITS)?,
+        };
+        parts.ipc_buffer_vaddr = Some(ipc_buffer_vaddr);
+
+        bi_mngr.map_paddr(
+            ipc_frame_cap,
+            ipc_buffer_vaddr,
+            CurrentArch::PAGE_BITS,
+            MapFlags::data(),
+        )?;
+        // unmapping the IPC buffer deletes the frame cap from now on
+        parts.ipc_frame_cap = None;
+
+        // set the IPC buffer's virtual address in a field of the IPC buffer
+        let ipc_buffer: *mut seL4_IPCBuffer = ipc_buffer_vaddr as _;
+        unsafe { (*ipc_buffer).userData = ipc_buffer_vaddr };
+
+        // mint a badged copy of the fault endpoint cap into our cspace
+        let (badged_fault_ep_cap, fault_ep_badge) = match self.fault_ep {
+            Some(fault_ep_cap) => {
+                let fault_ep_badge = init_sys.badges.alloc(BadgeKind::Fault)?;
+                parts.fault_ep_badge = Some(fault_ep_badge);
+                init_sys.threads.check_badge(fault_ep_badge)?;
+
+                let badged_cap = bi_mngr.alloc_slot()?;
+                parts.fault_ep_cap = Some(badged_cap);
+                mint(cspace_cap, fault_ep_cap, badged_cap, fault_ep_badge)?;
+                (Some(badged_cap), Some(fault_ep_badge))
+            }
+            None => (None, None),
+        };
+
+        let (badged_ipc_ep_cap, ipc_ep_badge) = if self.ipc_ep {
+            let ipc_ep_badge = init_sys.badges.alloc(BadgeKind::Ipc)?;
+            parts.ipc_ep_badge = Some(ipc_ep_badge);
+
+            let ipc_ep_cap =
+                bi_mngr.alloc_object(api_object_seL4_EndpointObject, seL4_EndpointBits as _)?;
+            parts.ipc_ep_obj_cap = Some(ipc_ep_cap);
+
+            let badged_cap = bi_mngr.alloc_slot()?;
+            parts.ipc_ep_cap = Some(badged_cap);
+            mint(cspace_cap, ipc_ep_cap, badged_cap, ipc_ep_badge)?;
+            (Some(badged_cap), Some(ipc_ep_badge))
+        } else {
+            (None, None)
+        };
+
+        let err: seL4_Error = unsafe {
+            seL4_TCB_Configure(
+                tcb_cap,
+                badged_fault_ep_cap.unwrap_or(seL4_CapNull as _),
+                cspace_cap.into(),
+                seL4_NilData.into(),
+                pd_cap.into(),
+                seL4_NilData.into(),
+                ipc_buffer_vaddr,
+                ipc_frame_cap,
+            )
+        };
+        Error::check(Operation::TCBConfigure, tcb_cap, err)?;
+
+        #[cfg(feature = "KernelDebugBuild")]
+        set_debug_name(tcb_cap, self.name);
+
+        let stack = alloc_stack(bi_mngr, self.stack_size)?;
+        parts.stack = Some(stack);
+
+        let err = unsafe {
+            seL4_TCB_SetMCPriority(
+                tcb_cap,
+                seL4_CapInitThreadTCB.into(),
+                self.max_priority as _,
+            )
+        };
+        Error::check(Operation::TCBSetMCPriority, tcb_cap, err)?;
+
+        let err = unsafe {
+            seL4_TCB_SetPriority(tcb_cap, seL4_CapInitThreadTCB.into(), self.priority as _)
+        };
+        Error::check(Operation::TCBSetPriority, tcb_cap, err)?;
+
+        let (start_data, restart_entry) = match self.entry {
+            Entry::Fn(entry) => {
+                // the badged IPC ep cap is the default function parameter
+                let arg = self.arg.or(badged_ipc_ep_cap).unwrap_or(0);
+                let restart_entry: Box<dyn Fn() -> StartData> =
+                    Box::new(move || StartData::new(move || entry(arg)));
+                (restart_entry(), Some(restart_entry))
+            }
+            Entry::Closure(data) => (data, None),
+        };
+
+        // the thread exits through its fault ep, see exit
+        let exit_ep = badged_fault_ep_cap.unwrap_or(seL4_CapNull as _);
+        let start_alloc = prepare(tcb_cap, &stack, exit_ep, start_data)?;
+
+        Ok(ThreadInfo {
+            name: self.name,
+            tcb_cap,
+            fault_ep_cap: badged_fault_ep_cap,
+            fault_ep_badge,
+            joinable: self.fault_ep == Some(init_sys.fault_ep_cap),
+            ipc_ep_cap: badged_ipc_ep_cap,
+            ipc_ep_badge,
+            ipc_buffer_vaddr,
+            stack,
+            start_alloc: Some(start_alloc),
+            fault_policy: self.fault_policy,
+            suspended_fault: None,
+            restart_entry,
+            num_restarts: 0,
+        })
+    }
+}
+
+/// What ThreadBuilder::spawn has created so far, released again if a later step fails
+struct SpawnParts {
+    tcb_cap: Option<seL4_CPtr>,
+    /// the IPC buffer frame until it is mapped
+    ipc_frame_cap: Option<seL4_CPtr>,
+    ipc_buffer_vaddr: Option<seL4_Word>,
+    fault_ep_cap: Option<seL4_CPtr>,
+    fault_ep_badge: Option<seL4_Word>,
+    ipc_ep_obj_cap: Option<seL4_CPtr>,
+    ipc_ep_cap: Option<seL4_CPtr>,
+    ipc_ep_badge: Option<seL4_Word>,
+    stack: Option<Region>,
+}
+
+impl SpawnParts {
+    fn new() -> SpawnParts {
+        SpawnParts {
+            tcb_cap: None,
+            ipc_frame_cap: None,
+            ipc_buffer_vaddr: None,
+            fault_ep_cap: None,
+            fault_ep_badge: None,
+            ipc_ep_obj_cap: None,
+            ipc_ep_cap: None,
+            ipc_ep_badge: None,
+            stack: None,
+        }
+    }
+
+    /// Releases the parts, the thread never ran so no one else holds its caps
+    fn free(self, bi_mngr: &mut BootInfoManager, badges: &mut BadgeAllocator) -> Result<(), Error> {
+        if let Some(tcb_cap) = self.tcb_cap {
+            bi_mngr.delete_cap(tcb_cap)?;
+        }
+
+        if let Some(vaddr) = self.ipc_buffer_vaddr {
+            bi_mngr.unmap_region(vaddr, 1 << CurrentArch::PAGE_BITS)?;
+            bi_mngr.free_vaddr(vaddr)?;
+        }
+        if let Some(frame_cap) = self.ipc_frame_cap {
+            bi_mngr.delete_cap(frame_cap)?;
+        }
+
+        if let Some(ref stack) = self.stack {
+            free_stack(bi_mngr, stack)?;
+        }
+
+        // minted slots may still be empty, deleting an empty slot succeeds
+        for cap in [self.fault_ep_cap, self.ipc_ep_cap, self.ipc_ep_obj_cap].iter() {
+            if let Some(cap) = *cap {
+                bi_mngr.delete_cap(cap)?;
+            }
+        }
+
+        for badge in [self.fault_ep_badge, self.ipc_ep_badge].iter() {
+            if let Some(badge) = *badge {
+                badges.free(badge)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Names the TCB in kernel scheduler dumps, long names are truncated
+#[cfg(feature = "KernelDebugBuild")]
+fn set_debug_name(tcb_cap: seL4_CPtr, name: &str) {
+    use core::cmp;
+
+    // the kernel expects a NUL terminated string
+    let mut c_name = [0u8; 32];
+    let len = cmp::min(name.len(), c_name.len() - 1);
+    c_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+    unsafe { seL4_DebugNameThread(tcb_cap, c_name.as_ptr() as *const _) };
+}
+
+/// Points the thread at the trampoline of start_data on the stack,
+/// it runs once resumed
+fn prepare(
+    tcb_cap: seL4_CPtr,
+    stack: &Region,
+    exit_ep: seL4_CPtr,
+    start_data: StartData,
+) -> Result<StartAlloc, Error> {
+    let mut regs: seL4_UserContext = unsafe { mem::zeroed() };
+    CurrentArch::init_user_context(
+        &mut regs,
+        start_data.trampoline,
+        stack.end,
+        [start_data.ptr as seL4_Word, exit_ep, tcb_cap],
+    );
+
+    let context_size = mem::size_of::<seL4_UserContext>() / mem::size_of::<seL4_Word>();
+    let err = unsafe { seL4_TCB_WriteRegisters(tcb_cap, 0, 0, context_size as _, &mut regs) };
+    Error::check(Operation::TCBWriteRegisters, tcb_cap, err)?;
+
+    Ok(start_data.into_alloc())
+}
+
+fn resume(tcb_cap: seL4_CPtr) -> Result<(), Error> {
+    let err = unsafe { seL4_TCB_Resume(tcb_cap) };
+    Error::check(Operation::TCBResume, tcb_cap, err)
+}
+
+/// Starts a faulted thread over from its entry point, the stack is reused
+pub fn restart(info: &mut ThreadInfo) -> Result<(), Error> {
+    let start_data = match info.restart_entry {
+        Some(ref restart_entry) => restart_entry(),
+        None => return Err(Error::NotRestartable { name: info.name }),
+    };
+
+    let err = unsafe { seL4_TCB_Suspend(info.tcb_cap) };
+    Error::check(Operation::TCBSuspend, info.tcb_cap, err)?;
+
+    if let Some(start_alloc) = info.start_alloc.take() {
+        unsafe { start_alloc.free() };
+    }
+
+    let exit_ep = info.fault_ep_cap.unwrap_or(seL4_CapNull as _);
+    info.start_alloc = Some(prepare(info.tcb_cap, &info.stack, exit_ep, start_data)?);
+    info.num_restarts += 1;
+
+    resume(info.tcb_cap)?;
+
+    Ok(())
+}
+
+/// Suspends the thread, it keeps all its resources
+pub fn suspend(info: &ThreadInfo) -> Result<(), Error> {
+    let err = unsafe { seL4_TCB_Suspend(info.tcb_cap) };
+    Error::check(Operation::TCBSuspend, info.tcb_cap, err)
+}
+
+/// Maps a stack of at least stack_size bytes into a free region of the VSpace,
+/// the page below it is reserved but left unmapped so an overflow faults
+/// instead of running into whatever is mapped below. stack_size is not 0, see spawn
+fn alloc_stack(bi_mngr: &mut BootInfoManager, stack_size: usize) -> Result<Region, Error> {
+    let page_size: usize = 1 << CurrentArch::PAGE_BITS;
+    let stack_size = (stack_size + page_size - 1) & !(page_size - 1);
+
+    let guard_vaddr = bi_mngr.alloc_vaddr(page_size + stack_size, CurrentArch::PAGE_BITS)?;
+    let stack = Region::new(guard_vaddr + page_size as seL4_Word, stack_size);
+
+    // map_region unmaps the frames it mapped before failing, so only the
+    // vaddr range is left to release
+    if let Err(e) = bi_mngr.map_region(stack.start, stack_size, MapFlags::data()) {
+        bi_mngr.free_vaddr(guard_vaddr)?;
+        return Err(e);
+    }
+
+    Ok(stack)
+}
+
+/// Unmaps the stack pages and releases the stack's vaddr range, guard page included
+fn free_stack(bi_mngr: &mut BootInfoManager, stack: &Region) -> Result<(), Error> {
+    bi_mngr.unmap_region(stack.start, stack.size())?;
+    bi_mngr.free_vaddr(stack.start - (1 << CurrentArch::PAGE_BITS))
+}
+
+/// Releases what a thread was created with: its TCB, IPC buffer, stack,
+/// fault endpoint cap and badge and start closure. Untyped memory is not
+/// reclaimed. The IPC endpoint, its badged cap and badge are kept, other
+/// threads may still hold copies of the cap in the shared CSpace
+pub fn teardown(
+    bi_mngr: &mut BootInfoManager,
+    badges: &mut BadgeAllocator,
+    info: ThreadInfo,
+) -> Result<(), Error> {
+    suspend(&info)?;
+    bi_mngr.delete_cap(info.tcb_cap)?;
+
+    // the thread can no longer run, its closure is gone
+    if let Some(start_alloc) = info.start_alloc {
+        unsafe { start_alloc.free() };
+    }
+
+    let page_size: usize = 1 << CurrentArch::PAGE_BITS;
+
+    bi_mngr.unmap_region(info.ipc_buffer_vaddr, page_size)?;
+    bi_mngr.free_vaddr(info.ipc_buffer_vaddr)?;
+
+    free_stack(bi_mngr, &info.stack)?;
+
+    // only the TCB held the badged fault ep cap
+    if let Some(cap) = info.fault_ep_cap {
+        bi_mngr.delete_cap(cap)?;
+    }
+    if let Some(badge) = info.fault_ep_badge {
+        badges.free(badge)?;
+    }
+
+    Ok(())
+}
+
+/// Mints a copy of ep_cap with the badge into the empty slot
+fn mint(
+    cspace_cap: seL4_CPtr,
+    ep_cap: seL4_CPtr,
+    slot_cap: seL4_CPtr,
+    badge: seL4_Word,
+) -> Result<(), Error> {
+    badge::validate(badge)?;
+
+    let err: seL4_Error = unsafe {
+        seL4_CNode_Mint(
+            cspace_cap,
+            slot_cap,
+            seL4_WordBits as _,
+            cspace_cap,
+            ep_cap,
+            seL4_WordBits as _,
+            seL4_CapRights_new(1, 1, 1),
+            badge,
+        )
+    };
+    Error::check(Operation::CNodeMint, ep_cap, err)
+}
@@ -1,28 +1,33 @@
-use sel4_sys::{seL4_CPtr, seL4_MessageInfo_new, seL4_Recv, seL4_Reply, seL4_Word};
+use ipc::Server;
+use sel4_sys::{seL4_CPtr, seL4_Word};
 
 #[cfg(feature = "KernelPrinting")]
 use sel4_sys::DebugOutHandle;
 
-pub const FAULT_EP_BADGE: seL4_Word = 0x0B;
-pub const IPC_EP_BADGE: seL4_Word = 0x1B;
+declare_interface! {
+    /// Service thread B offers thread A
+    pub interface PingPong {
+        client: PingPongClient,
+        labels: PingPongLabel,
+        /// Echoes the sequence number
+        fn ping(seq: seL4_Word) -> seL4_Word;
+    }
+}
+
+struct PingPongServer;
 
-/// arbitrary (but free) address for IPC buffer
-pub const IPC_BUFFER_VADDR: seL4_Word = 0x0700_1000;
+impl PingPong for PingPongServer {
+    fn ping(&mut self, _client_badge: seL4_Word, seq: seL4_Word) -> seL4_Word {
+        debug_println!("thread_b::got msg from A, sending reply");
+        seq
+    }
+}
 
 pub fn run(ep_cap: seL4_CPtr) {
     debug_println!("thread_b::run()");
     debug_println!("thread_b::ep_cap = 0x{:X}", ep_cap,);
 
-    for _ in 0..10 {
-        let mut badge: seL4_Word = 0;
-        let _msg_info = unsafe { seL4_Recv(ep_cap, &mut badge) };
-
-        debug_println!("thread_b::got msg from A, sending reply");
-
-        let resp_info = unsafe { seL4_MessageInfo_new(IPC_EP_BADGE, 0, 0, 0) };
-
-        unsafe { seL4_Reply(resp_info) };
-    }
+    PingPongServer::register(Server::new(ep_cap, PingPongServer)).serve(10);
 
     debug_println!("thread_b::done");
 }
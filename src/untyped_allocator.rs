@@ -0,0 +1,184 @@
+use alloc::vec::Vec;
+use sel4_sys::{seL4_BootInfo, seL4_CPtr, seL4_Word};
+
+/// Small objects are served from untypeds split off into chunks of this size
+/// so they don't fragment the large untypeds needed for large frames
+pub const CHUNK_SIZE_BITS: usize = 16;
+
+/// Bookkeeping for a single untyped cap, either from the bootinfo
+/// untypedList or split off of one
+#[derive(Debug, Copy, Clone)]
+pub struct UntypedRecord {
+    pub cap: seL4_CPtr,
+    pub paddr: seL4_Word,
+    pub size_bits: usize,
+    pub is_device: bool,
+    /// offset of the first unused byte, mirrors the kernel's FreeIndex
+    pub watermark: usize,
+}
+
+impl UntypedRecord {
+    pub fn size_bytes(&self) -> usize {
+        1 << self.size_bits
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.size_bytes() - self.watermark
+    }
+
+    /// Physical address a new object of the given size would be placed at
+    pub fn next_paddr(&self, size_bits: usize) -> seL4_Word {
+        self.paddr + self.next_offset(size_bits) as seL4_Word
+    }
+
+    /// Offset a new object of the given size would be placed at, the kernel
+    /// aligns each object to its own size
+    fn next_offset(&self, size_bits: usize) -> usize {
+        align_up(self.watermark, 1 << size_bits)
+    }
+
+    /// True if the physical address range lies within this untyped
+    pub fn contains(&self, paddr: seL4_Word, size_bytes: usize) -> bool {
+        let offset = match paddr.checked_sub(self.paddr) {
+            Some(offset) => offset as usize,
+            None => return false,
+        };
+
+        offset < self.size_bytes() && size_bytes <= self.size_bytes() - offset
+    }
+
+    fn fits(&self, size_bits: usize, num_objects: usize) -> bool {
+        size_bits <= self.size_bits
+            && self.next_offset(size_bits) + (num_objects << size_bits) <= self.size_bytes()
+    }
+}
+
+/// Tracks how much of each untyped has been retyped
+pub struct UntypedAllocator {
+    untypeds: Vec<UntypedRecord>,
+}
+
+impl UntypedAllocator {
+    pub fn new(bootinfo: &'static seL4_BootInfo) -> UntypedAllocator {
+        let mut untypeds = Vec::new();
+
+        for cap in bootinfo.untyped.start..bootinfo.untyped.end {
+            let desc = &bootinfo.untypedList[(cap - bootinfo.untyped.start) as usize];
+            untypeds.push(UntypedRecord {
+                cap,
+                paddr: desc.paddr,
+                size_bits: desc.sizeBits as usize,
+                is_device: desc.isDevice != 0,
+                watermark: 0,
+            });
+        }
+
+        UntypedAllocator { untypeds }
+    }
+
+    /// Total number of RAM bytes not yet retyped, device memory is not included
+    pub fn free_bytes(&self) -> usize {
+        self.untypeds
+            .iter()
+            .filter(|u| !u.is_device)
+            .map(|u| u.free_bytes())
+            .sum()
+    }
+
+    /// Smallest RAM untyped with room for `num_objects` objects of 2^size_bits bytes,
+    /// returns the index of its record.
+    /// Device untypeds are never returned, the kernel only allows
+    /// frames to be created from device memory.
+    pub fn find(&self, size_bits: usize, num_objects: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+
+        for (idx, untyped) in self.untypeds.iter().enumerate() {
+            if untyped.is_device || !untyped.fits(size_bits, num_objects) {
+                continue;
+            }
+
+            best = match best {
+                Some(best_idx) if self.untypeds[best_idx].size_bits <= untyped.size_bits => {
+                    Some(best_idx)
+                }
+                _ => Some(idx),
+            };
+        }
+
+        best
+    }
+
+    /// Index of the smallest device untyped containing the physical
+    /// address range [paddr, paddr + size_bytes)
+    pub fn find_device(&self, paddr: seL4_Word, size_bytes: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+
+        for (idx, untyped) in self.untypeds.iter().enumerate() {
+            if !untyped.is_device || !untyped.contains(paddr, size_bytes) {
+                continue;
+            }
+
+            best = match best {
+                Some(best_idx) if self.untypeds[best_idx].size_bits <= untyped.size_bits => {
+                    Some(best_idx)
+                }
+                _ => Some(idx),
+            };
+        }
+
+        best
+    }
+
+    /// True if the untyped should be split into chunks before serving
+    /// `num_objects` objects of 2^size_bits bytes, i.e. the objects are
+    /// smaller than a chunk and a whole chunk still fits after alignment
+    pub fn should_split(&self, idx: usize, size_bits: usize, num_objects: usize) -> bool {
+        let untyped = &self.untypeds[idx];
+
+        (num_objects << size_bits) < (1 << CHUNK_SIZE_BITS)
+            && untyped.size_bits > CHUNK_SIZE_BITS
+            && untyped.fits(CHUNK_SIZE_BITS, 1)
+    }
+
+    /// Records the retype of `num_objects` objects from the untyped,
+    /// returns the physical address of the first object
+    pub fn consume(&mut self, idx: usize, size_bits: usize, num_objects: usize) -> seL4_Word {
+        let untyped = &mut self.untypeds[idx];
+        assert!(
+            untyped.fits(size_bits, num_objects),
+            "Untyped does not have enough free space"
+        );
+
+        let offset = untyped.next_offset(size_bits);
+        untyped.watermark = offset + (num_objects << size_bits);
+
+        untyped.paddr + offset as seL4_Word
+    }
+
+    /// Adds a child untyped retyped from a parent with consume()
+    pub fn add_child(
+        &mut self,
+        cap: seL4_CPtr,
+        paddr: seL4_Word,
+        size_bits: usize,
+        is_device: bool,
+    ) -> usize {
+        self.untypeds.push(UntypedRecord {
+            cap,
+            paddr,
+            size_bits,
+            is_device,
+            watermark: 0,
+        });
+
+        self.untypeds.len() - 1
+    }
+
+    pub fn get(&self, idx: usize) -> &UntypedRecord {
+        &self.untypeds[idx]
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
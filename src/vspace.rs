@@ -0,0 +1,288 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use arch::{Arch, CurrentArch};
+use error::Error;
+use sel4_sys::{seL4_CPtr, seL4_CapRights_new, seL4_CapRights_t, seL4_Word};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Normal memory, i.e. RAM
+    Cached,
+    /// Device registers
+    Uncached,
+    /// Uncached but writes may be combined, i.e. frame buffers,
+    /// falls back to Uncached where the architecture has no such attribute
+    WriteCombine,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rights {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Rights {
+    pub fn cap_rights(&self) -> seL4_CapRights_t {
+        let cap_grant = 0;
+        let cap_read = 1;
+        let cap_write = match *self {
+            Rights::ReadOnly => 0,
+            Rights::ReadWrite => 1,
+        };
+
+        unsafe { seL4_CapRights_new(cap_grant, cap_read, cap_write) }
+    }
+}
+
+/// How a frame is mapped into the VSpace
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MapFlags {
+    pub cache: CachePolicy,
+    pub rights: Rights,
+    pub executable: bool,
+}
+
+impl MapFlags {
+    /// Cached, read-write, execute-never; for IPC buffers, stacks and heaps
+    pub fn data() -> MapFlags {
+        MapFlags {
+            cache: CachePolicy::Cached,
+            rights: Rights::ReadWrite,
+            executable: false,
+        }
+    }
+
+    /// Cached, read-only, executable
+    pub fn code() -> MapFlags {
+        MapFlags {
+            cache: CachePolicy::Cached,
+            rights: Rights::ReadOnly,
+            executable: true,
+        }
+    }
+
+    /// Uncached, read-write, execute-never; for memory mapped IO device regions
+    pub fn device() -> MapFlags {
+        MapFlags {
+            cache: CachePolicy::Uncached,
+            rights: Rights::ReadWrite,
+            executable: false,
+        }
+    }
+}
+
+/// Half-open range of virtual addresses [start, end)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: seL4_Word,
+    pub end: seL4_Word,
+}
+
+impl Region {
+    pub fn new(start: seL4_Word, size: usize) -> Region {
+        Region {
+            start,
+            end: start + size as seL4_Word,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn contains(&self, vaddr: seL4_Word) -> bool {
+        vaddr >= self.start && vaddr < self.end
+    }
+
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// A frame mapped into the VSpace
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub frame_cap: seL4_CPtr,
+    pub size_bits: usize,
+}
+
+/// Keeps track of the root task's VSpace, reserved regions and mapped pages
+pub struct VSpaceManager {
+    /// reserved regions, sorted by start address and non-overlapping
+    reserved: Vec<Region>,
+    /// mapped frames keyed by their vaddr
+    mappings: BTreeMap<seL4_Word, Mapping>,
+    /// paging structures keyed by their level and the base vaddr of the
+    /// area they cover, None for structures the kernel created for us
+    paging_structures: BTreeMap<(usize, seL4_Word), Option<seL4_CPtr>>,
+    /// reserved regions backed by frames on first access
+    lazy_regions: Vec<(Region, MapFlags)>,
+}
+
+impl VSpaceManager {
+    pub fn new() -> VSpaceManager {
+        VSpaceManager {
+            reserved: Vec::new(),
+            mappings: BTreeMap::new(),
+            paging_structures: BTreeMap::new(),
+            lazy_regions: Vec::new(),
+        }
+    }
+
+    /// Reserves the fixed region [vaddr, vaddr + size)
+    pub fn reserve(&mut self, vaddr: seL4_Word, size: usize) -> Result<(), Error> {
+        let region = Region::new(vaddr, size);
+
+        if self.reserved.iter().any(|r| r.overlaps(&region)) {
+            return Err(Error::VaddrInUse { vaddr, size });
+        }
+
+        let idx = self
+            .reserved
+            .iter()
+            .position(|r| r.start > region.start)
+            .unwrap_or(self.reserved.len());
+        self.reserved.insert(idx, region);
+
+        Ok(())
+    }
+
+    /// Reserves a free region of size bytes aligned to 2^align_bits,
+    /// returns the start of the region
+    pub fn alloc(&mut self, size: usize, align_bits: usize) -> Result<seL4_Word, Error> {
+        let align_mask: seL4_Word = (1 << align_bits) - 1;
+        let mut candidate = (CurrentArch::VSPACE_START + align_mask) & !align_mask;
+        let mut insert_idx = 0;
+
+        for (idx, r) in self.reserved.iter().enumerate() {
+            if r.end <= candidate {
+                insert_idx = idx + 1;
+                continue;
+            }
+
+            if candidate + size as seL4_Word <= r.start {
+                break;
+            }
+
+            candidate = (r.end + align_mask) & !align_mask;
+            insert_idx = idx + 1;
+        }
+
+        if candidate + size as seL4_Word > CurrentArch::VSPACE_END {
+            return Err(Error::VSpaceExhausted { size });
+        }
+
+        self.reserved
+            .insert(insert_idx, Region::new(candidate, size));
+
+        Ok(candidate)
+    }
+
+    /// Releases the reserved region starting at vaddr, returns the region
+    pub fn free(&mut self, vaddr: seL4_Word) -> Option<Region> {
+        self.lazy_regions.retain(|&(r, _)| r.start != vaddr);
+
+        let idx = self.reserved.iter().position(|r| r.start == vaddr)?;
+        Some(self.reserved.remove(idx))
+    }
+
+    /// Marks the reserved region as demand paged, pages are mapped with flags
+    pub fn add_lazy_region(&mut self, region: Region, flags: MapFlags) {
+        self.lazy_regions.push((region, flags));
+    }
+
+    /// Returns the lazy region containing vaddr and its map flags
+    pub fn find_lazy_region(&self, vaddr: seL4_Word) -> Option<(Region, MapFlags)> {
+        self.lazy_regions
+            .iter()
+            .find(|&&(r, _)| r.contains(vaddr))
+            .cloned()
+    }
+
+    /// Returns the reserved region containing vaddr
+    pub fn find_region(&self, vaddr: seL4_Word) -> Option<Region> {
+        self.reserved.iter().find(|r| r.contains(vaddr)).cloned()
+    }
+
+    /// Records a frame of 2^size_bits bytes mapped at vaddr, fails if it is
+    /// not within a reserved region or overlaps an existing mapping
+    pub fn add_mapping(
+        &mut self,
+        vaddr: seL4_Word,
+        size_bits: usize,
+        frame_cap: seL4_CPtr,
+    ) -> Result<(), Error> {
+        let end = vaddr + (1 << size_bits);
+
+        if !self
+            .reserved
+            .iter()
+            .any(|r| r.start <= vaddr && end <= r.end)
+        {
+            return Err(Error::VaddrNotReserved {
+                vaddr,
+                size: 1 << size_bits,
+            });
+        }
+
+        let overlaps = self
+            .mappings
+            .range(..end)
+            .next_back()
+            .map_or(false, |(&start, m)| start + (1 << m.size_bits) > vaddr);
+
+        if overlaps {
+            return Err(Error::VaddrInUse {
+                vaddr,
+                size: 1 << size_bits,
+            });
+        }
+
+        self.mappings.insert(
+            vaddr,
+            Mapping {
+                frame_cap,
+                size_bits,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Removes the mapping record of the frame mapped at vaddr
+    pub fn remove_mapping(&mut self, vaddr: seL4_Word) -> Option<Mapping> {
+        self.mappings.remove(&vaddr)
+    }
+
+    /// Returns the vaddr and mapping of the frame containing vaddr
+    pub fn find_mapping(&self, vaddr: seL4_Word) -> Option<(seL4_Word, Mapping)> {
+        self.mappings
+            .range(..(vaddr + 1))
+            .next_back()
+            .filter(|&(&start, m)| start + (1 << m.size_bits) > vaddr)
+            .map(|(&start, m)| (start, *m))
+    }
+
+    pub fn is_mapped(&self, vaddr: seL4_Word) -> bool {
+        self.find_mapping(vaddr).is_some()
+    }
+
+    /// True if a paging structure of PAGING_LEVELS[level] covering vaddr is present
+    pub fn has_paging_structure(&self, level: usize, vaddr: seL4_Word) -> bool {
+        self.paging_structures
+            .contains_key(&(level, paging_structure_base(level, vaddr)))
+    }
+
+    /// Records the paging structure mapped to cover vaddr, cap is None
+    /// if the structure was already present
+    pub fn add_paging_structure(&mut self, level: usize, vaddr: seL4_Word, cap: Option<seL4_CPtr>) {
+        let prev = self
+            .paging_structures
+            .insert((level, paging_structure_base(level, vaddr)), cap);
+        assert!(prev.is_none(), "Paging structure already present");
+    }
+}
+
+fn paging_structure_base(level: usize, vaddr: seL4_Word) -> seL4_Word {
+    vaddr & !((1 << CurrentArch::PAGING_LEVELS[level].coverage_bits) - 1)
+}